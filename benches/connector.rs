@@ -0,0 +1,81 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, Uri};
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::TcpListener;
+
+use hyper_timeout::TimeoutConnector;
+
+/// Response body sizes, requested as the path of the URL.
+const SIZES: [usize; 3] = [16, 64 * 1024, 4 * 1024 * 1024];
+
+async fn serve(listener: TcpListener) {
+    while let Ok((stream, _addr)) = listener.accept().await {
+        tokio::spawn(async move {
+            let service = service_fn(|req: Request<Incoming>| async move {
+                let size = req.uri().path()[1..].parse::<usize>().unwrap_or(0);
+                Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(vec![0; size]))))
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+async fn get<C>(client: &Client<C, Empty<Bytes>>, uri: Uri)
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    let res = client.get(uri).await.expect("request failed");
+    res.into_body().collect().await.expect("body failed");
+}
+
+fn bench_get(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let addr = rt.block_on(async {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+        addr
+    });
+
+    let raw = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(HttpConnector::new());
+
+    let mut connector = TimeoutConnector::new(HttpConnector::new());
+    connector.set_connect_timeout(Some(Duration::from_secs(5)));
+    connector.set_read_timeout(Some(Duration::from_secs(5)));
+    connector.set_write_timeout(Some(Duration::from_secs(5)));
+    let timeout = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+
+    let mut group = c.benchmark_group("get");
+    for size in SIZES {
+        let uri: Uri = format!("http://{}/{}", addr, size).parse().unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("hyper", size), &uri, |b, uri| {
+            b.to_async(&rt).iter(|| get(&raw, uri.clone()))
+        });
+        group.bench_with_input(BenchmarkId::new("hyper-timeout", size), &uri, |b, uri| {
+            b.to_async(&rt).iter(|| get(&timeout, uri.clone()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_get);
+criterion_main!(benches);
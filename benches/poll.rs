@@ -0,0 +1,147 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use hyper::rt::{Read, ReadBuf, ReadBufCursor};
+use tokio::pin;
+
+use hyper_timeout::TimeoutReader;
+
+/// A reader which never has data and never wakes, so every poll is spent in the timeout.
+struct Pending;
+
+impl Read for Pending {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        _buf: ReadBufCursor,
+    ) -> Poll<Result<(), io::Error>> {
+        Poll::Pending
+    }
+}
+
+/// How often a waker was cloned and woken.
+#[derive(Default)]
+struct Counts {
+    clones: AtomicUsize,
+    wakes: AtomicUsize,
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+unsafe fn clone(data: *const ()) -> RawWaker {
+    (*(data as *const Counts))
+        .clones
+        .fetch_add(1, Ordering::Relaxed);
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn wake(data: *const ()) {
+    (*(data as *const Counts))
+        .wakes
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+unsafe fn drop(_data: *const ()) {}
+
+/// Returns a waker counting its clones and wakes in `counts`.
+fn counting_waker(counts: &'static Counts) -> Waker {
+    let data = counts as *const Counts as *const ();
+    // SAFETY: the vtable only reads the counts, which live for the rest of the program.
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+/// Polls a pending reader with a read timeout `polls` times, returning how often the wakers were cloned and woken.
+fn count_polls(polls: usize, alternate: bool) -> (usize, usize) {
+    let counts: &'static [Counts; 2] = Box::leak(Box::default());
+    let wakers = [counting_waker(&counts[0]), counting_waker(&counts[1])];
+    let mut data = [0u8; 1];
+
+    let mut reader = TimeoutReader::new(Pending);
+    reader.set_timeout(Some(Duration::from_secs(60)));
+    pin!(reader);
+
+    for i in 0..polls {
+        let mut cx = Context::from_waker(&wakers[i * alternate as usize % 2]);
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(reader
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+    }
+
+    let sum = |count: fn(&Counts) -> &AtomicUsize| {
+        counts
+            .iter()
+            .map(|c| count(c).load(Ordering::Relaxed))
+            .sum()
+    };
+    (sum(|c| &c.clones), sum(|c| &c.wakes))
+}
+
+/// Polls a pending reader with a read timeout, either with the same waker every time, which leaves the timer as it
+/// was registered, or alternating between two wakers, which registers the timer again on every poll.
+///
+/// The number of waker clones and wakes is checked before timing the polls: with the same waker, the timer is
+/// registered once however often the reader is polled, and no poll wakes the task.
+fn bench_poll(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let _guard = rt.enter();
+
+    const POLLS: usize = 1000;
+    for (name, alternate) in [("same waker", false), ("new waker", true)] {
+        let (clones, wakes) = count_polls(POLLS, alternate);
+        println!(
+            "poll_read/{}: {} waker clones and {} wakes in {} polls",
+            name, clones, wakes, POLLS
+        );
+        assert_eq!(wakes, 0, "{}: a pending poll woke the task", name);
+        if alternate {
+            assert!(
+                clones >= POLLS,
+                "{}: the new waker was not registered",
+                name
+            );
+        } else {
+            assert_eq!(
+                clones,
+                count_polls(1, false).0,
+                "{}: the waker was registered again",
+                name
+            );
+        }
+    }
+
+    let wakers = [
+        counting_waker(Box::leak(Box::default())),
+        counting_waker(Box::leak(Box::default())),
+    ];
+    let mut data = [0u8; 1];
+
+    let mut group = c.benchmark_group("poll_read");
+    for (name, alternate) in [("same waker", false), ("new waker", true)] {
+        let mut reader = TimeoutReader::new(Pending);
+        reader.set_timeout(Some(Duration::from_secs(60)));
+        pin!(reader);
+
+        let mut i = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                i += alternate as usize;
+                let mut cx = Context::from_waker(&wakers[i % 2]);
+                let mut buf = ReadBuf::new(&mut data);
+                reader.as_mut().poll_read(&mut cx, buf.unfilled())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_poll);
+criterion_main!(benches);
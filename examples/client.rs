@@ -40,7 +40,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     while let Some(frame) = res.body_mut().frame().await {
         let bytes = frame?
             .into_data()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Error when consuming frame"))?;
+            .map_err(|_| io::Error::other("Error when consuming frame"))?;
         io::stdout().write_all(&bytes).await?;
     }
 
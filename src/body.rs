@@ -0,0 +1,275 @@
+//! A body wrapper which applies a timeout between frames.
+//!
+//! The write timeout on the stream only sees bytes which have already been produced by the request body. When the
+//! body is fed by a slow or stalled producer, hyper simply stops writing and no write is ever pending. Wrapping the
+//! body in a [`TimeoutBody`] bounds the time the producer may take to yield each frame instead.
+//!
+//! A response body can also be bounded by the progress of its consumer rather than by bytes on the socket: the
+//! consumer reports progress through a [`Checkpoint`], and the body fails when no checkpoint was reported for too long.
+#![warn(missing_docs)]
+
+use hyper::body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::error::Phase;
+use crate::stream::TimeoutState;
+use crate::BoxError;
+
+pin_project! {
+    /// A [`Body`] which applies a timeout to each frame produced by the inner body.
+    ///
+    /// The countdown is initiated when polling the inner body for a frame returns [`Poll::Pending`]. If no frame is
+    /// produced before the countdown expires, an [`io::Error`](std::io::Error) with a kind of
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) is returned, with a [`Phase::Body`](crate::Phase::Body)
+    /// [`TimeoutError`](crate::TimeoutError) as its inner error.
+    #[derive(Debug)]
+    pub struct TimeoutBody<B> {
+        #[pin]
+        body: B,
+        #[pin]
+        state: TimeoutState,
+        checkpoint: Option<Checkpoint>,
+        // counts down from the last checkpoint, once a handle has been taken
+        #[pin]
+        checkpoint_state: TimeoutState,
+    }
+}
+
+impl<B> TimeoutBody<B> {
+    /// Returns a new `TimeoutBody` wrapping the specified body.
+    ///
+    /// There is initially no timeout.
+    pub fn new(body: B) -> TimeoutBody<B> {
+        TimeoutBody {
+            body,
+            state: TimeoutState::new(Phase::Body),
+            checkpoint: None,
+            checkpoint_state: TimeoutState::new(Phase::Checkpoint),
+        }
+    }
+
+    /// Returns the current frame timeout.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.state.timeout()
+    }
+
+    /// Sets the frame timeout.
+    ///
+    /// This can only be used before the body is pinned; use [`set_timeout_pinned`](Self::set_timeout_pinned)
+    /// otherwise.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.state.set_timeout(timeout);
+    }
+
+    /// Sets the frame timeout.
+    ///
+    /// This will reset any pending timeout. Use [`set_timeout`](Self::set_timeout) instead if the body is not yet
+    /// pinned.
+    pub fn set_timeout_pinned(self: Pin<&mut Self>, timeout: Option<Duration>) {
+        self.project().state.set_timeout_pinned(timeout);
+    }
+
+    /// Returns the checkpoint timeout.
+    pub fn checkpoint_timeout(&self) -> Option<Duration> {
+        self.checkpoint_state.timeout()
+    }
+
+    /// Sets the maximum time allowed between two checkpoints.
+    ///
+    /// Once a handle has been taken with [`checkpoint`](Self::checkpoint), polling the body fails with a
+    /// [`Phase::Checkpoint`](crate::Phase::Checkpoint) error when no checkpoint was reported for longer than this,
+    /// counting from when the handle was taken. This measures the progress of whatever consumes the body, such as
+    /// persisting each frame, rather than how fast bytes arrive. The timeout has no effect until a handle is taken.
+    ///
+    /// This can only be used before the body is pinned.
+    pub fn set_checkpoint_timeout(&mut self, timeout: Option<Duration>) {
+        self.checkpoint_state.set_timeout(timeout);
+    }
+
+    /// Returns a handle for reporting checkpoints on this body.
+    ///
+    /// Every call returns a handle to the same checkpoint, which starts when the first handle is taken.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use http_body_util::Empty;
+    /// use hyper::body::Bytes;
+    /// use hyper_timeout::TimeoutBody;
+    ///
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let mut body = TimeoutBody::new(Empty::<Bytes>::new());
+    /// body.set_checkpoint_timeout(Some(Duration::from_secs(30)));
+    /// let checkpoint = body.checkpoint();
+    ///
+    /// // after persisting each frame read from the body
+    /// checkpoint.checkpoint();
+    /// # }
+    /// ```
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoint.get_or_insert_with(Checkpoint::new).clone()
+    }
+
+    /// Returns a shared reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.body
+    }
+
+    /// Returns a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.body
+    }
+
+    /// Returns a pinned mutable reference to the inner body.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut B> {
+        self.project().body
+    }
+
+    /// Consumes the `TimeoutBody`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.body
+    }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = B::Data;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        if let Some(checkpoint) = this.checkpoint {
+            // the countdown restarts from whenever the consumer last reported progress
+            this.checkpoint_state.as_mut().start(checkpoint.last());
+            this.checkpoint_state.poll_expired(cx)?;
+        }
+        match this.body.poll_frame(cx) {
+            Poll::Pending => {
+                this.state.poll_check(cx)?;
+                Poll::Pending
+            }
+            Poll::Ready(frame) => {
+                this.state.reset();
+                Poll::Ready(frame.map(|frame| frame.map_err(Into::into)))
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// A handle for reporting the progress of a [`TimeoutBody`]'s consumer, returned by [`TimeoutBody::checkpoint`].
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    last: Arc<Mutex<Instant>>,
+}
+
+impl Checkpoint {
+    fn new() -> Checkpoint {
+        Checkpoint {
+            last: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Reports that the consumer has made progress, restarting the checkpoint timeout.
+    pub fn checkpoint(&self) {
+        *self.last.lock().unwrap() = Instant::now();
+    }
+
+    /// Returns when progress was last reported, or when the checkpoint was created if it never was.
+    pub fn last(&self) -> Instant {
+        *self.last.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use std::convert::Infallible;
+    use std::io;
+    use tokio::pin;
+
+    struct PendingBody;
+
+    impl Body for PendingBody {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn frame_timeout() {
+        let mut body = TimeoutBody::new(PendingBody);
+        body.set_timeout(Some(Duration::from_millis(100)));
+        pin!(body);
+
+        let e = body.frame().await.unwrap().unwrap_err();
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn checkpoint_timeout() {
+        let mut body = TimeoutBody::new(PendingBody);
+        body.set_checkpoint_timeout(Some(Duration::from_millis(100)));
+        let checkpoint = body.checkpoint();
+        pin!(body);
+
+        tokio::join!(
+            async {
+                let e = body.frame().await.unwrap().unwrap_err();
+                let io_e = e.downcast_ref::<io::Error>().unwrap();
+                assert_eq!(
+                    io_e.to_string(),
+                    "checkpoint timed out after 100ms (timeout 100ms)"
+                );
+            },
+            async {
+                // checkpoints keep the body alive past the timeout
+                for _ in 0..3 {
+                    tokio::time::sleep(Duration::from_millis(60)).await;
+                    checkpoint.checkpoint();
+                }
+            }
+        );
+        assert_eq!(checkpoint.last().elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn frame_ok() {
+        let mut body = TimeoutBody::new(Full::new(Bytes::from_static(b"hello")));
+        body.set_timeout(Some(Duration::from_millis(100)));
+        pin!(body);
+
+        let frame = body.frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "hello");
+        assert!(body.frame().await.is_none());
+    }
+}
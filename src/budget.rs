@@ -0,0 +1,126 @@
+//! A time budget which can be split between the phases of a request.
+//!
+//! The budget is measured against the tokio clock, so it behaves the same as the timeouts applied by the connector,
+//! including when time is paused in tests. The connector itself uses it to cap the connect timeout by the total
+//! timeout and by the connect and task deadlines.
+
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A fixed amount of time, starting when the budget is created, which can be handed out in pieces.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    total: Duration,
+    start: Instant,
+}
+
+impl Budget {
+    /// Returns a new `Budget` of `total` starting now.
+    pub fn new(total: Duration) -> Budget {
+        Budget {
+            total,
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns a new `Budget` starting now and ending at `deadline`.
+    ///
+    /// The budget is already exhausted if the deadline has passed.
+    pub fn until(deadline: Instant) -> Budget {
+        let start = Instant::now();
+        Budget {
+            total: deadline.saturating_duration_since(start),
+            start,
+        }
+    }
+
+    /// Returns the total size of the budget.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Returns the amount of time spent since the budget was created.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Returns the amount of time left in the budget.
+    ///
+    /// This is zero once the budget is exhausted.
+    pub fn remaining(&self) -> Duration {
+        self.total.saturating_sub(self.elapsed())
+    }
+
+    /// Returns the instant at which the budget is exhausted, if it can be represented.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.start.checked_add(self.total)
+    }
+
+    /// Returns `timeout`, shortened to the time left in the budget, so that an operation started now with the
+    /// returned timeout cannot outlast the budget.
+    pub fn cap(&self, timeout: Duration) -> Duration {
+        timeout.min(self.remaining())
+    }
+
+    /// Returns true if there is no time left in the budget.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == Duration::ZERO
+    }
+
+    /// Returns the given fraction of the remaining budget.
+    ///
+    /// The fraction is clamped between `0.0` and `1.0`. A `NaN` fraction is treated as `0.0`.
+    pub fn take(&self, fraction: f64) -> Duration {
+        if fraction.is_nan() || fraction <= 0.0 {
+            return Duration::ZERO;
+        }
+        self.remaining().mul_f64(fraction.min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remaining() {
+        let budget = Budget::new(Duration::from_secs(10));
+        let remaining = budget.remaining();
+        assert!(remaining <= Duration::from_secs(10));
+        assert!(remaining > Duration::from_secs(9));
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn exhausted() {
+        let budget = Budget::new(Duration::ZERO);
+        assert_eq!(budget.remaining(), Duration::ZERO);
+        assert_eq!(budget.take(0.5), Duration::ZERO);
+        assert!(budget.is_exhausted());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn until() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let budget = Budget::until(deadline);
+        assert_eq!(budget.deadline(), Some(deadline));
+        assert_eq!(budget.cap(Duration::from_secs(4)), Duration::from_secs(4));
+
+        tokio::time::advance(Duration::from_secs(8)).await;
+        assert_eq!(budget.cap(Duration::from_secs(4)), Duration::from_secs(2));
+        tokio::time::advance(Duration::from_secs(3)).await;
+        assert_eq!(budget.cap(Duration::from_secs(4)), Duration::ZERO);
+
+        assert!(Budget::until(deadline).is_exhausted());
+    }
+
+    #[test]
+    fn take() {
+        let budget = Budget::new(Duration::from_secs(10));
+        assert!(budget.take(0.5) <= Duration::from_secs(5));
+        assert!(budget.take(0.5) > Duration::from_secs(4));
+        assert!(budget.take(2.0) <= Duration::from_secs(10));
+        assert_eq!(budget.take(-1.0), Duration::ZERO);
+        assert_eq!(budget.take(f64::NAN), Duration::ZERO);
+    }
+}
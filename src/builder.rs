@@ -0,0 +1,491 @@
+//! A fluent builder for `TimeoutConnector`.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use hyper::Uri;
+use tokio::time::Instant;
+
+use crate::{
+    BoxError, HostPattern, StallDetector, TimeoutConnector, TimeoutError, TimeoutPolicy, Timeouts,
+};
+
+/// The largest timeout [`TimeoutConnectorBuilder::build`] accepts, one day.
+///
+/// A timeout longer than that is far more likely to be a configuration mistake, such as milliseconds given as
+/// seconds, than an intended limit, and would arm a timer which in practice never fires.
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An error returned when building a [`TimeoutConnector`] from an invalid configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// Neither [`connect_timeout`](TimeoutConnectorBuilder::connect_timeout) nor
+    /// [`no_connect_timeout`](TimeoutConnectorBuilder::no_connect_timeout) was called.
+    MissingConnectTimeout,
+    /// The named timeout was set to zero, which would fail every operation immediately.
+    ZeroTimeout(&'static str),
+    /// The named timeout is longer than [`MAX_TIMEOUT`].
+    TimeoutTooLarge(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingConnectTimeout => f.write_str(
+                "connect timeout is not set; use `no_connect_timeout` to connect without one",
+            ),
+            ConfigError::ZeroTimeout(name) => {
+                write!(f, "{} timeout must be greater than zero", name)
+            }
+            ConfigError::TimeoutTooLarge(name) => {
+                write!(f, "{} timeout must be at most {:?}", name, MAX_TIMEOUT)
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// A builder for a [`TimeoutConnector`], created with [`TimeoutConnector::builder`].
+///
+/// The connect timeout must be chosen explicitly, either with [`connect_timeout`](Self::connect_timeout) or
+/// [`no_connect_timeout`](Self::no_connect_timeout), so it cannot be forgotten.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use hyper_timeout::TimeoutConnector;
+/// use hyper_util::client::legacy::connect::HttpConnector;
+///
+/// let connector = TimeoutConnector::builder(HttpConnector::new())
+///     .connect_timeout(Duration::from_secs(5))
+///     .read_timeout(Duration::from_secs(30))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TimeoutConnectorBuilder<T> {
+    connector: TimeoutConnector<T>,
+    connect_timeout_set: bool,
+    clamp_timeouts: bool,
+}
+
+impl<T> TimeoutConnectorBuilder<T> {
+    pub(crate) fn new(connector: TimeoutConnector<T>) -> TimeoutConnectorBuilder<T> {
+        TimeoutConnectorBuilder {
+            connector,
+            connect_timeout_set: false,
+            clamp_timeouts: false,
+        }
+    }
+
+    /// Sets the timeout for the inner connector to become ready.
+    ///
+    /// See [`TimeoutConnector::set_ready_timeout`].
+    pub fn ready_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_ready_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for connecting to a URL.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_connect_timeout(Some(timeout));
+        self.connect_timeout_set = true;
+        self
+    }
+
+    /// Connects without a timeout.
+    pub fn no_connect_timeout(mut self) -> Self {
+        self.connector.set_connect_timeout(None);
+        self.connect_timeout_set = true;
+        self
+    }
+
+    /// Sets the timeout for resolving the host name.
+    ///
+    /// See [`TimeoutConnector::set_dns_timeout`].
+    pub fn dns_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_dns_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets an instant after which connecting fails.
+    ///
+    /// See [`TimeoutConnector::set_connect_deadline`].
+    pub fn connect_deadline(mut self, deadline: Instant) -> Self {
+        self.connector.set_connect_deadline(Some(deadline));
+        self
+    }
+
+    /// Sets the timeout for connecting to a loopback destination.
+    ///
+    /// See [`TimeoutConnector::set_loopback_connect_timeout`].
+    pub fn loopback_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_loopback_connect_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for the response.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_read_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for the request.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_write_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout between writing a request and the first byte of the response.
+    ///
+    /// See [`TimeoutConnector::set_first_byte_timeout`].
+    pub fn first_byte_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_first_byte_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for uploading a request as a whole.
+    ///
+    /// See [`TimeoutConnector::set_upload_timeout`].
+    pub fn upload_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_upload_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for downloading a response as a whole.
+    ///
+    /// See [`TimeoutConnector::set_download_timeout`].
+    pub fn download_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_download_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets a stall detector for downloads.
+    ///
+    /// See [`TimeoutConnector::set_stall_detector`].
+    pub fn stall_detector(mut self, detector: StallDetector) -> Self {
+        self.connector.set_stall_detector(Some(detector));
+        self
+    }
+
+    /// Sets the timeout for the stream making no progress.
+    ///
+    /// See [`TimeoutConnector::set_idle_timeout`].
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_idle_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the maximum time the stream may go without progress in either direction.
+    ///
+    /// See [`TimeoutConnector::set_max_silence`].
+    pub fn max_silence(mut self, timeout: Duration) -> Self {
+        self.connector.set_max_silence(Some(timeout));
+        self
+    }
+
+    /// Sets the timeout for shutting down the stream.
+    ///
+    /// See [`TimeoutConnector::set_shutdown_timeout`].
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_shutdown_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets the overall timeout, from starting to connect until the stream is closed.
+    ///
+    /// See [`TimeoutConnector::set_total_timeout`].
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.connector.set_total_timeout(Some(timeout));
+        self
+    }
+
+    /// Resets the reader timeout on write.
+    ///
+    /// See [`TimeoutConnector::set_reset_reader_on_write`].
+    pub fn reset_reader_on_write(mut self, reset: bool) -> Self {
+        self.connector.set_reset_reader_on_write(reset);
+        self
+    }
+
+    /// Clamps timeouts longer than [`MAX_TIMEOUT`] to it instead of rejecting them.
+    ///
+    /// By default, [`build`](Self::build) fails when any timeout is longer than `MAX_TIMEOUT`. Use
+    /// [`build_with_report`](Self::build_with_report) to find out which timeouts were clamped.
+    pub fn clamp_timeouts(mut self, clamp: bool) -> Self {
+        self.clamp_timeouts = clamp;
+        self
+    }
+
+    /// Sets the maximum number of bytes passed to a single write on the stream.
+    ///
+    /// See [`TimeoutConnector::set_max_write_size`].
+    pub fn max_write_size(mut self, size: usize) -> Self {
+        self.connector.set_max_write_size(Some(size));
+        self
+    }
+
+    /// Sets a policy choosing the timeouts for each destination.
+    ///
+    /// See [`TimeoutConnector::set_timeout_policy`].
+    pub fn timeout_policy(mut self, policy: impl TimeoutPolicy + 'static) -> Self {
+        self.connector.set_timeout_policy(policy);
+        self
+    }
+
+    /// Sets a function choosing the timeouts for each destination.
+    ///
+    /// See [`TimeoutConnector::set_timeout_fn`].
+    pub fn timeout_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Uri) -> Timeouts + Send + Sync + 'static,
+    {
+        self.connector.set_timeout_fn(f);
+        self
+    }
+
+    /// Sets timeouts for connections to hosts matching `pattern`.
+    ///
+    /// See [`TimeoutConnector::set_host_timeouts`].
+    pub fn host_timeouts(mut self, pattern: impl Into<HostPattern>, timeouts: Timeouts) -> Self {
+        self.connector.set_host_timeouts(pattern, timeouts);
+        self
+    }
+
+    /// Sets timeouts for connections to destinations with the given scheme.
+    ///
+    /// See [`TimeoutConnector::set_scheme_timeouts`].
+    pub fn scheme_timeouts(mut self, scheme: &str, timeouts: Timeouts) -> Self {
+        self.connector.set_scheme_timeouts(scheme, timeouts);
+        self
+    }
+
+    /// Only allows connections to hosts matching one of the given patterns.
+    ///
+    /// See [`TimeoutConnector::set_allowed_hosts`].
+    pub fn allowed_hosts<I>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<HostPattern>,
+    {
+        self.connector.set_allowed_hosts(hosts);
+        self
+    }
+
+    /// Denies connections to hosts matching any of the given patterns.
+    ///
+    /// See [`TimeoutConnector::set_denied_hosts`].
+    pub fn denied_hosts<I>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<HostPattern>,
+    {
+        self.connector.set_denied_hosts(hosts);
+        self
+    }
+
+    /// Rejects connections whose remote address is private.
+    ///
+    /// See [`TimeoutConnector::set_deny_private_addrs`].
+    pub fn deny_private_addrs(mut self, deny: bool) -> Self {
+        self.connector.set_deny_private_addrs(deny);
+        self
+    }
+
+    /// Leaves hosts and addresses out of error messages.
+    ///
+    /// See [`TimeoutConnector::set_redact_errors`].
+    pub fn redact_errors(mut self, redact: bool) -> Self {
+        self.connector.set_redact_errors(redact);
+        self
+    }
+
+    /// Sets a function converting the connector's timeouts into the application's own errors.
+    ///
+    /// See [`TimeoutConnector::set_error_mapper`].
+    pub fn error_mapper<F>(mut self, f: F) -> Self
+    where
+        F: Fn(TimeoutError) -> BoxError + Send + Sync + 'static,
+    {
+        self.connector.set_error_mapper(f);
+        self
+    }
+
+    /// Writes a line for every connection attempt to `sink`.
+    ///
+    /// See [`TimeoutConnector::set_connect_log`].
+    pub fn connect_log(mut self, sink: impl std::io::Write + Send + 'static) -> Self {
+        self.connector.set_connect_log(sink);
+        self
+    }
+
+    /// Returns the configured `TimeoutConnector`.
+    ///
+    /// This fails if the connect timeout was not chosen, if any timeout is zero, or if any timeout is longer than
+    /// [`MAX_TIMEOUT`] and [`clamp_timeouts`](Self::clamp_timeouts) is not set.
+    pub fn build(self) -> Result<TimeoutConnector<T>, ConfigError> {
+        self.build_with_report().map(|(connector, _)| connector)
+    }
+
+    /// Returns the configured `TimeoutConnector` along with the names of the timeouts which were clamped to
+    /// [`MAX_TIMEOUT`].
+    ///
+    /// This fails in the same cases as [`build`](Self::build). Timeouts of host and scheme rules are reported as
+    /// `"host"` and `"scheme"`, the same names [`ConfigError`] uses for them.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use hyper_timeout::TimeoutConnector;
+    /// use hyper_util::client::legacy::connect::HttpConnector;
+    ///
+    /// let (_connector, clamped) = TimeoutConnector::builder(HttpConnector::new())
+    ///     .connect_timeout(Duration::from_secs(5))
+    ///     // meant to be 3600 seconds
+    ///     .read_timeout(Duration::from_secs(3_600_000))
+    ///     .clamp_timeouts(true)
+    ///     .build_with_report()
+    ///     .unwrap();
+    /// assert_eq!(clamped, ["read"]);
+    /// ```
+    pub fn build_with_report(
+        self,
+    ) -> Result<(TimeoutConnector<T>, Vec<&'static str>), ConfigError> {
+        if !self.connect_timeout_set {
+            return Err(ConfigError::MissingConnectTimeout);
+        }
+
+        let mut connector = self.connector;
+        let clamp = self.clamp_timeouts;
+        let timeouts = [
+            ("ready", &mut connector.ready_timeout),
+            ("dns", &mut connector.dns_timeout),
+            ("connect", &mut connector.connect_timeout),
+            ("loopback connect", &mut connector.loopback_connect_timeout),
+            ("read", &mut connector.read_timeout),
+            ("write", &mut connector.write_timeout),
+            ("first byte", &mut connector.first_byte_timeout),
+            ("upload", &mut connector.upload_timeout),
+            ("download", &mut connector.download_timeout),
+            ("idle", &mut connector.idle_timeout),
+            ("max silence", &mut connector.max_silence),
+            ("shutdown", &mut connector.shutdown_timeout),
+            ("total", &mut connector.total_timeout),
+        ];
+        let mut clamped = Vec::new();
+        for (name, timeout) in timeouts {
+            if check_timeout(name, timeout, clamp)? {
+                clamped.push(name);
+            }
+        }
+        // rules for hosts and schemes override the timeouts above, so they are checked the same way
+        let host_timeouts = connector.host_timeouts.iter_mut().map(|(_, t)| ("host", t));
+        let scheme_timeouts = connector
+            .scheme_timeouts
+            .iter_mut()
+            .map(|(_, t)| ("scheme", t));
+        for (name, timeouts) in host_timeouts.chain(scheme_timeouts) {
+            for timeout in [
+                &mut timeouts.connect,
+                &mut timeouts.read,
+                &mut timeouts.write,
+            ] {
+                if check_timeout(name, timeout, clamp)? {
+                    clamped.push(name);
+                }
+            }
+        }
+
+        Ok((connector, clamped))
+    }
+}
+
+/// Rejects a zero timeout, and rejects or clamps one longer than [`MAX_TIMEOUT`].
+///
+/// Returns true if the timeout was clamped.
+fn check_timeout(
+    name: &'static str,
+    timeout: &mut Option<Duration>,
+    clamp: bool,
+) -> Result<bool, ConfigError> {
+    match *timeout {
+        Some(t) if t.is_zero() => Err(ConfigError::ZeroTimeout(name)),
+        Some(t) if t > MAX_TIMEOUT && clamp => {
+            *timeout = Some(MAX_TIMEOUT);
+            Ok(true)
+        }
+        Some(t) if t > MAX_TIMEOUT => Err(ConfigError::TimeoutTooLarge(name)),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper_util::client::legacy::connect::HttpConnector;
+
+    #[test]
+    fn build() {
+        let connector = TimeoutConnector::builder(HttpConnector::new())
+            .connect_timeout(Duration::from_secs(5))
+            .read_timeout(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        assert_eq!(connector.connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(connector.read_timeout, Some(Duration::from_secs(30)));
+        assert_eq!(connector.write_timeout, None);
+
+        let connector = TimeoutConnector::builder(HttpConnector::new())
+            .no_connect_timeout()
+            .build()
+            .unwrap();
+        assert_eq!(connector.connect_timeout, None);
+    }
+
+    #[test]
+    fn build_invalid() {
+        let err = TimeoutConnector::builder(HttpConnector::new())
+            .read_timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::MissingConnectTimeout);
+
+        let err = TimeoutConnector::builder(HttpConnector::new())
+            .connect_timeout(Duration::from_secs(5))
+            .write_timeout(Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::ZeroTimeout("write"));
+
+        let err = TimeoutConnector::builder(HttpConnector::new())
+            .connect_timeout(Duration::from_secs(5))
+            .read_timeout(Duration::from_secs(3_600_000))
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::TimeoutTooLarge("read"));
+    }
+
+    #[test]
+    fn build_clamped() {
+        let (connector, clamped) = TimeoutConnector::builder(HttpConnector::new())
+            .connect_timeout(Duration::from_secs(5))
+            .read_timeout(Duration::from_secs(3_600_000))
+            .host_timeouts(
+                "*.internal",
+                Timeouts {
+                    write: Some(Duration::from_secs(3_600_000)),
+                    ..Timeouts::default()
+                },
+            )
+            .clamp_timeouts(true)
+            .build_with_report()
+            .unwrap();
+        assert_eq!(clamped, ["read", "host"]);
+        assert_eq!(connector.connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(connector.read_timeout, Some(MAX_TIMEOUT));
+        assert_eq!(connector.host_timeouts[0].1.write, Some(MAX_TIMEOUT));
+    }
+}
@@ -0,0 +1,107 @@
+//! A plain-text log of connection attempts.
+//!
+//! The log is written directly to a sink chosen by the application, independently of any logging framework, so it
+//! can be turned on for field debugging without reconfiguring the application's logging.
+
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{timeout_phase, BoxError};
+
+/// A shared sink which receives one line per connection attempt.
+#[derive(Clone)]
+pub(crate) struct ConnectLog(Arc<Mutex<dyn Write + Send>>);
+
+impl ConnectLog {
+    pub(crate) fn new(sink: impl Write + Send + 'static) -> ConnectLog {
+        ConnectLog(Arc::new(Mutex::new(sink)))
+    }
+
+    /// Writes the line for one connection attempt, with `-` for the host if it is `None`.
+    ///
+    /// This blocks on the lock and on the sink. Failing to write is ignored, so a broken sink never fails a connection.
+    pub(crate) fn record(&self, host: Option<&str>, elapsed: Duration, err: Option<&BoxError>) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:03} host={} elapsed={:?}",
+            now.as_secs(),
+            now.subsec_millis(),
+            host.unwrap_or("-"),
+            elapsed
+        );
+        match err {
+            None => line.push_str(" outcome=connected"),
+            Some(err) => match timeout_phase(&**err) {
+                Some(phase) => line.push_str(&format!(
+                    " outcome=timeout phase=\"{}\" error=\"{}\"",
+                    phase, err
+                )),
+                None => line.push_str(&format!(" outcome=failed error=\"{}\"", err)),
+            },
+        }
+        line.push('\n');
+
+        let mut sink = match self.0.lock() {
+            Ok(sink) => sink,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let _ = sink.write_all(line.as_bytes()).and_then(|()| sink.flush());
+    }
+}
+
+impl fmt::Debug for ConnectLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConnectLog")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    use crate::{Phase, TimeoutError};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn record() {
+        let buf = SharedBuf::default();
+        let log = ConnectLog::new(buf.clone());
+
+        log.record(Some("example.com"), Duration::from_millis(12), None);
+        let err: BoxError = Box::new(io::Error::from(TimeoutError::new(Phase::Connect)));
+        log.record(Some("example.com"), Duration::from_secs(5), Some(&err));
+        let err: BoxError = Box::new(io::Error::from(io::ErrorKind::ConnectionRefused));
+        log.record(None, Duration::from_millis(1), Some(&err));
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = out
+            .lines()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "host=example.com elapsed=12ms outcome=connected",
+                "host=example.com elapsed=5s outcome=timeout phase=\"connect\" error=\"connect timed out\"",
+                "host=- elapsed=1ms outcome=failed error=\"connection refused\"",
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! A deadline carried by the current task.
+//!
+//! Code which cannot reach the connector, such as a handler several layers above the client, can still bound the
+//! connects made on its behalf by running them inside [`with_deadline`].
+
+use std::future::Future;
+
+use tokio::time::Instant;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Runs `fut` with `deadline` as the deadline of the current task.
+///
+/// A [`TimeoutConnector`](crate::TimeoutConnector) called while `fut` runs gives up connecting at the deadline if it
+/// comes before the connector's own connect timeout, failing with a [`Phase::Deadline`](crate::Phase::Deadline)
+/// error. Deadlines can be nested, in which case the sooner one applies.
+///
+/// The deadline only bounds connecting. A connection outlives the request it was made for and is reused from the
+/// pool by later requests, so the deadline is not applied to reads and writes on the stream; bound the request
+/// itself, for example with [`timeout_future`](crate::timeout_future), for that.
+///
+/// ```
+/// # async fn run() {
+/// use std::time::Duration;
+///
+/// use hyper_timeout::with_deadline;
+/// use tokio::time::Instant;
+///
+/// let deadline = Instant::now() + Duration::from_secs(2);
+/// with_deadline(deadline, async {
+///     // connects made by a client with a `TimeoutConnector` end by the deadline
+/// })
+/// .await;
+/// # }
+/// ```
+pub async fn with_deadline<F: Future>(deadline: Instant, fut: F) -> F::Output {
+    let deadline = match current_deadline() {
+        Some(outer) => outer.min(deadline),
+        None => deadline,
+    };
+    DEADLINE.scope(deadline, fut).await
+}
+
+/// Returns the deadline of the current task, if it is running inside [`with_deadline`].
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn nested() {
+        assert_eq!(current_deadline(), None);
+
+        let soon = Instant::now() + Duration::from_secs(1);
+        let later = soon + Duration::from_secs(1);
+        with_deadline(soon, async {
+            assert_eq!(current_deadline(), Some(soon));
+            with_deadline(later, async {
+                assert_eq!(current_deadline(), Some(soon));
+            })
+            .await;
+        })
+        .await;
+
+        with_deadline(later, async {
+            with_deadline(soon, async {
+                assert_eq!(current_deadline(), Some(soon));
+            })
+            .await;
+            assert_eq!(current_deadline(), Some(later));
+        })
+        .await;
+    }
+}
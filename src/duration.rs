@@ -0,0 +1,112 @@
+//! Parsing of human readable durations such as `5s` or `250ms`.
+//!
+//! [`parse_duration`] has the signature expected of a `clap` value parser, so command line tools can expose timeout
+//! flags with `#[arg(value_parser = hyper_timeout::parse_duration)]` without any glue code.
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// An error returned when parsing a duration fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDurationError {
+    reason: &'static str,
+}
+
+impl ParseDurationError {
+    fn new(reason: &'static str) -> ParseDurationError {
+        ParseDurationError { reason }
+    }
+}
+
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid duration: {}", self.reason)
+    }
+}
+
+impl Error for ParseDurationError {}
+
+/// Parses a duration made of a number followed by a unit.
+///
+/// The supported units are `ns`, `us`, `ms`, `s`, `m` and `h`. The number may have a fractional part, as in `1.5s`.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// assert_eq!(hyper_timeout::parse_duration("5s"), Ok(Duration::from_secs(5)));
+/// assert_eq!(hyper_timeout::parse_duration("250ms"), Ok(Duration::from_millis(250)));
+/// ```
+pub fn parse_duration(s: &str) -> Result<Duration, ParseDurationError> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .ok_or_else(|| ParseDurationError::new("missing unit"))?;
+    let (value, unit) = s.split_at(split);
+    let unit = unit.trim_start();
+
+    if value.is_empty() {
+        return Err(ParseDurationError::new("missing number"));
+    }
+
+    if value.contains('.') {
+        let value = value
+            .parse::<f64>()
+            .map_err(|_| ParseDurationError::new("invalid number"))?;
+        let secs = match unit {
+            "ns" => value / 1_000_000_000.0,
+            "us" => value / 1_000_000.0,
+            "ms" => value / 1_000.0,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3_600.0,
+            _ => return Err(ParseDurationError::new("unknown unit")),
+        };
+        return Duration::try_from_secs_f64(secs)
+            .map_err(|_| ParseDurationError::new("duration is too large"));
+    }
+
+    let value = value
+        .parse::<u64>()
+        .map_err(|_| ParseDurationError::new("duration is too large"))?;
+    let duration = match unit {
+        "ns" => Some(Duration::from_nanos(value)),
+        "us" => Some(Duration::from_micros(value)),
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => value.checked_mul(60).map(Duration::from_secs),
+        "h" => value.checked_mul(3_600).map(Duration::from_secs),
+        _ => return Err(ParseDurationError::new("unknown unit")),
+    };
+    duration.ok_or_else(|| ParseDurationError::new("duration is too large"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_duration("10ns"), Ok(Duration::from_nanos(10)));
+        assert_eq!(parse_duration("10us"), Ok(Duration::from_micros(10)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3_600)));
+        assert_eq!(parse_duration(" 5 s "), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1_500)));
+        assert_eq!(parse_duration("0.5m"), Ok(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("1.2.3s").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration("9999999999999999999h").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+//! The error reported when a timeout expires.
+//!
+//! Timeouts on streams and connections are still reported as an [`io::Error`] with a kind of
+//! [`TimedOut`](io::ErrorKind::TimedOut), as hyper expects. The inner error of that `io::Error` is a [`TimeoutError`],
+//! which tells which timeout expired and, when they are known, the destination host, the configured timeout and how
+//! long was waited.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Response, StatusCode};
+
+use crate::BoxError;
+
+/// The timeout which expired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Resolving the destination host name.
+    Dns,
+    /// Waiting for the inner connector to be ready.
+    Ready,
+    /// Connecting.
+    Connect,
+    /// A read on the stream.
+    Read,
+    /// A write on the stream.
+    Write,
+    /// Waiting for the first byte of a response.
+    FirstByte,
+    /// Uploading a request as a whole.
+    Upload,
+    /// Downloading a response as a whole.
+    Download,
+    /// A download transferring too few bytes for its [`StallDetector`](crate::StallDetector).
+    Stall,
+    /// The stream making no progress while an operation was pending.
+    Idle,
+    /// The stream making no progress, whether or not it was polled.
+    Silence,
+    /// Shutting down the stream.
+    Shutdown,
+    /// The total timeout or an absolute deadline.
+    Deadline,
+    /// Waiting for a frame of a [`TimeoutBody`](crate::TimeoutBody).
+    Body,
+    /// Waiting for a [`Checkpoint`](crate::Checkpoint) on a body.
+    Checkpoint,
+    /// An application defined phase, for use with [`timeout_future`](crate::timeout_future).
+    Custom(&'static str),
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Dns => "dns resolution",
+            Phase::Ready => "readiness",
+            Phase::Connect => "connect",
+            Phase::Read => "read",
+            Phase::Write => "write",
+            Phase::FirstByte => "first byte",
+            Phase::Upload => "upload",
+            Phase::Download => "download",
+            Phase::Stall => "download stall",
+            Phase::Idle => "idle",
+            Phase::Silence => "silence",
+            Phase::Shutdown => "shutdown",
+            Phase::Deadline => "deadline",
+            Phase::Body => "body frame",
+            Phase::Checkpoint => "checkpoint",
+            Phase::Custom(name) => name,
+        })
+    }
+}
+
+/// A coarse category of timeout, for labelling metrics.
+///
+/// Unlike [`Phase`], which names each timeout the crate has, this groups them into a small, fixed set, so it is safe to
+/// use as a metric label. Variants and their labels will not change; new variants may be added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TimeoutKind {
+    /// Resolving the destination host name.
+    Dns,
+    /// Getting a connection, including waiting for the inner connector to be ready.
+    Connect,
+    /// Receiving a response: reads, the first byte and the whole download.
+    Read,
+    /// Sending a request: writes and the whole upload.
+    Write,
+    /// The stream making no progress.
+    Idle,
+    /// Shutting down the stream.
+    Shutdown,
+    /// The total timeout or an absolute deadline.
+    Deadline,
+    /// Producing or consuming a body.
+    Body,
+    /// An application defined timeout.
+    Other,
+}
+
+impl TimeoutKind {
+    /// Returns the label of this kind, in snake case.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeoutKind::Dns => "dns",
+            TimeoutKind::Connect => "connect",
+            TimeoutKind::Read => "read",
+            TimeoutKind::Write => "write",
+            TimeoutKind::Idle => "idle",
+            TimeoutKind::Shutdown => "shutdown",
+            TimeoutKind::Deadline => "deadline",
+            TimeoutKind::Body => "body",
+            TimeoutKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An error returned when a timeout expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutError {
+    phase: Phase,
+    host: Option<String>,
+    timeout: Option<Duration>,
+    elapsed: Option<Duration>,
+}
+
+impl TimeoutError {
+    /// Returns a new `TimeoutError` for the given phase.
+    pub fn new(phase: Phase) -> TimeoutError {
+        TimeoutError {
+            phase,
+            host: None,
+            timeout: None,
+            elapsed: None,
+        }
+    }
+
+    pub(crate) fn with_host(mut self, host: Option<&str>) -> TimeoutError {
+        self.host = host.map(str::to_owned);
+        self
+    }
+
+    pub(crate) fn with_timeout(mut self, timeout: Option<Duration>) -> TimeoutError {
+        self.timeout = timeout;
+        self
+    }
+
+    pub(crate) fn with_elapsed(mut self, elapsed: Duration) -> TimeoutError {
+        self.elapsed = Some(elapsed);
+        self
+    }
+
+    /// Returns the timeout which expired.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Returns the category of the timeout which expired.
+    pub fn kind(&self) -> TimeoutKind {
+        match self.phase {
+            Phase::Dns => TimeoutKind::Dns,
+            Phase::Ready | Phase::Connect => TimeoutKind::Connect,
+            Phase::Read | Phase::FirstByte | Phase::Download | Phase::Stall => TimeoutKind::Read,
+            Phase::Write | Phase::Upload => TimeoutKind::Write,
+            Phase::Idle | Phase::Silence => TimeoutKind::Idle,
+            Phase::Shutdown => TimeoutKind::Shutdown,
+            Phase::Deadline => TimeoutKind::Deadline,
+            Phase::Body | Phase::Checkpoint => TimeoutKind::Body,
+            Phase::Custom(_) => TimeoutKind::Other,
+        }
+    }
+
+    /// Returns the destination host, if it is known and errors are not redacted.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Returns the configured value of the timeout which expired, if there is one.
+    ///
+    /// This is `None` for an absolute deadline.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Returns how long was waited before the timeout expired, if it was measured.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+
+    /// Returns the HTTP status a proxy should answer with when it gives up because of this timeout.
+    ///
+    /// Timeouts reading or writing an established connection, including the idle, shutdown and body timeouts, map to
+    /// `408 Request Timeout`. Timeouts reaching the server, that is resolving, connecting or waiting for the
+    /// connector, map to `504 Gateway Timeout`, as do deadlines spanning the whole exchange and custom phases.
+    pub fn as_status(&self) -> StatusCode {
+        match self.kind() {
+            TimeoutKind::Read
+            | TimeoutKind::Write
+            | TimeoutKind::Idle
+            | TimeoutKind::Shutdown
+            | TimeoutKind::Body => StatusCode::REQUEST_TIMEOUT,
+            TimeoutKind::Dns
+            | TimeoutKind::Connect
+            | TimeoutKind::Deadline
+            | TimeoutKind::Other => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} timed out", self.phase)?;
+        if let Some(host) = &self.host {
+            write!(f, " for {}", host)?;
+        }
+        match (self.elapsed, self.timeout) {
+            (Some(elapsed), Some(timeout)) => {
+                write!(f, " after {:?} (timeout {:?})", elapsed, timeout)
+            }
+            (Some(elapsed), None) => write!(f, " after {:?}", elapsed),
+            (None, Some(timeout)) => write!(f, " (timeout {:?})", timeout),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl Error for TimeoutError {}
+
+impl From<TimeoutError> for io::Error {
+    fn from(e: TimeoutError) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, e)
+    }
+}
+
+/// A shared function converting the connector's timeouts into application errors.
+#[derive(Clone)]
+pub(crate) struct ErrorMapper(Arc<dyn Fn(TimeoutError) -> BoxError + Send + Sync>);
+
+impl ErrorMapper {
+    pub(crate) fn new<F>(f: F) -> ErrorMapper
+    where
+        F: Fn(TimeoutError) -> BoxError + Send + Sync + 'static,
+    {
+        ErrorMapper(Arc::new(f))
+    }
+
+    /// Converts `err` with the mapper if there is one, or into an [`io::Error`] otherwise.
+    pub(crate) fn map(mapper: Option<&ErrorMapper>, err: TimeoutError) -> BoxError {
+        match mapper {
+            Some(mapper) => (mapper.0)(err),
+            None => Box::new(io::Error::from(err)),
+        }
+    }
+}
+
+impl fmt::Debug for ErrorMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorMapper")
+    }
+}
+
+/// Returns true if `err`, or any error in its chain of sources, is a timeout.
+///
+/// This recognises a [`TimeoutError`] as well as any [`io::Error`] with a kind of
+/// [`TimedOut`](io::ErrorKind::TimedOut), so it also detects timeouts which did not come from this crate.
+///
+/// ```
+/// use hyper_timeout::is_timeout;
+/// use hyper_util::client::legacy::Error;
+///
+/// fn should_retry(e: &Error) -> bool {
+///     is_timeout(e)
+/// }
+/// ```
+pub fn is_timeout(err: &(dyn Error + 'static)) -> bool {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if err.is::<TimeoutError>() {
+            return true;
+        }
+        if let Some(io_err) = err.downcast_ref::<io::Error>() {
+            if io_err.kind() == io::ErrorKind::TimedOut {
+                return true;
+            }
+            // `io::Error::source` skips the error it wraps, so look at that separately
+            if let Some(inner) = io_err.get_ref() {
+                if is_timeout(inner) {
+                    return true;
+                }
+            }
+        }
+        next = err.source();
+    }
+    false
+}
+
+/// Returns the phase of the first [`TimeoutError`] in the chain of sources of `err`.
+///
+/// Returns `None` if there is none, including for timeouts which did not come from this crate.
+pub fn timeout_phase(err: &(dyn Error + 'static)) -> Option<Phase> {
+    find_timeout_error(err).map(TimeoutError::phase)
+}
+
+/// Returns a response for the timeout in the chain of sources of `err`, or `None` if `err` is not a timeout.
+///
+/// The status is chosen by [`TimeoutError::as_status`]. A timeout which did not come from this crate is answered with
+/// `504 Gateway Timeout`. The body is left empty.
+///
+/// ```
+/// use http_body_util::Empty;
+/// use hyper::body::Bytes;
+/// use hyper::{Response, StatusCode};
+/// use hyper_timeout::timeout_response;
+///
+/// fn error_response(e: &hyper_util::client::legacy::Error) -> Response<Empty<Bytes>> {
+///     timeout_response(e).unwrap_or_else(|| {
+///         let mut res = Response::new(Empty::new());
+///         *res.status_mut() = StatusCode::BAD_GATEWAY;
+///         res
+///     })
+/// }
+/// ```
+pub fn timeout_response<B: Default>(err: &(dyn Error + 'static)) -> Option<Response<B>> {
+    let status = match find_timeout_error(err) {
+        Some(timeout_err) => timeout_err.as_status(),
+        None if is_timeout(err) => StatusCode::GATEWAY_TIMEOUT,
+        None => return None,
+    };
+    let mut res = Response::new(B::default());
+    *res.status_mut() = status;
+    Some(res)
+}
+
+fn find_timeout_error<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a TimeoutError> {
+    let mut next = Some(err);
+    while let Some(err) = next {
+        if let Some(timeout_err) = err.downcast_ref::<TimeoutError>() {
+            return Some(timeout_err);
+        }
+        // `io::Error::source` skips the error it wraps, so look at that separately
+        if let Some(inner) = err.downcast_ref::<io::Error>().and_then(io::Error::get_ref) {
+            if let Some(timeout_err) = find_timeout_error(inner) {
+                return Some(timeout_err);
+            }
+        }
+        next = err.source();
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapper(io::Error);
+
+    impl fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("request failed")
+        }
+    }
+
+    impl Error for Wrapper {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn into_io_error() {
+        let e = io::Error::from(TimeoutError::new(Phase::FirstByte));
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(e.to_string(), "first byte timed out");
+
+        let inner = e.get_ref().unwrap().downcast_ref::<TimeoutError>().unwrap();
+        assert_eq!(inner.phase(), Phase::FirstByte);
+    }
+
+    #[test]
+    fn find_timeout() {
+        let e = Wrapper(TimeoutError::new(Phase::Connect).into());
+        assert!(is_timeout(&e));
+        assert_eq!(timeout_phase(&e), Some(Phase::Connect));
+
+        let e = Wrapper(io::Error::from(io::ErrorKind::TimedOut));
+        assert!(is_timeout(&e));
+        assert_eq!(timeout_phase(&e), None);
+
+        let e = Wrapper(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(!is_timeout(&e));
+        assert_eq!(timeout_phase(&e), None);
+    }
+
+    #[test]
+    fn status() {
+        let e = Wrapper(TimeoutError::new(Phase::Connect).into());
+        let res = timeout_response::<()>(&e).unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let e = Wrapper(TimeoutError::new(Phase::Body).into());
+        let res = timeout_response::<()>(&e).unwrap();
+        assert_eq!(res.status(), StatusCode::REQUEST_TIMEOUT);
+
+        for phase in [Phase::Read, Phase::Write, Phase::FirstByte, Phase::Idle] {
+            assert_eq!(
+                TimeoutError::new(phase).as_status(),
+                StatusCode::REQUEST_TIMEOUT
+            );
+        }
+        for phase in [
+            Phase::Dns,
+            Phase::Ready,
+            Phase::Deadline,
+            Phase::Custom("retry"),
+        ] {
+            assert_eq!(
+                TimeoutError::new(phase).as_status(),
+                StatusCode::GATEWAY_TIMEOUT
+            );
+        }
+
+        let e = Wrapper(io::Error::from(io::ErrorKind::TimedOut));
+        let res = timeout_response::<()>(&e).unwrap();
+        assert_eq!(res.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let e = Wrapper(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert!(timeout_response::<()>(&e).is_none());
+    }
+
+    #[test]
+    fn kind() {
+        assert_eq!(TimeoutError::new(Phase::Ready).kind(), TimeoutKind::Connect);
+        assert_eq!(
+            TimeoutError::new(Phase::FirstByte).kind(),
+            TimeoutKind::Read
+        );
+        assert_eq!(TimeoutError::new(Phase::Silence).kind(), TimeoutKind::Idle);
+        let kind = TimeoutError::new(Phase::Custom("handshake")).kind();
+        assert_eq!(kind.as_str(), "other");
+    }
+
+    #[test]
+    fn display() {
+        let e = TimeoutError::new(Phase::Connect)
+            .with_host(Some("example.com"))
+            .with_timeout(Some(Duration::from_secs(5)))
+            .with_elapsed(Duration::from_millis(5002));
+        assert_eq!(
+            e.to_string(),
+            "connect timed out for example.com after 5.002s (timeout 5s)"
+        );
+
+        let e = TimeoutError::new(Phase::Read).with_timeout(Some(Duration::from_millis(100)));
+        assert_eq!(e.to_string(), "read timed out (timeout 100ms)");
+    }
+}
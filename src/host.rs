@@ -0,0 +1,411 @@
+//! Matching of destination hosts.
+
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use hyper::Uri;
+
+/// A normalized destination host and port.
+///
+/// The host is lowercased, with any IPv6 brackets and trailing dot removed, and the port is dropped when it is the
+/// default for the scheme, so `http://Example.com.:80` and `http://example.com` have the same key. Host patterns and
+/// the loopback check are applied to this key.
+///
+/// Internationalized domain names are already in their ASCII form in a `Uri`, so they need no further handling.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HostKey {
+    host: String,
+    port: Option<u16>,
+}
+
+impl HostKey {
+    /// Returns the key for the destination `uri`, or `None` if it has no host.
+    pub fn from_uri(uri: &Uri) -> Option<HostKey> {
+        let host = normalize(uri.host()?).to_ascii_lowercase();
+        let default_port = match uri.scheme_str() {
+            Some("http") => Some(80),
+            Some("https") => Some(443),
+            _ => None,
+        };
+        let port = uri.port_u16().filter(|port| Some(*port) != default_port);
+        Some(HostKey { host, port })
+    }
+
+    /// Returns the normalized host.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the port, or `None` if it is the default for the scheme.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+}
+
+impl fmt::Display for HostKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            f.write_str(&self.host)?;
+        }
+        match self.port {
+            Some(port) => write!(f, ":{}", port),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A pattern matching destination hosts.
+///
+/// `example.com` matches only that host, `*.example.com` matches any subdomain of `example.com` and `*` matches every
+/// host. A `*` anywhere else is not a wildcard, so `*example.com` matches no host. Patterns and hosts are normalized
+/// like a [`HostKey`]: matching ignores ASCII case, a trailing dot and the brackets around an IPv6 address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPattern {
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    Any,
+    Exact(String),
+    // stored with the leading dot, e.g. `.example.com`
+    Suffix(String),
+}
+
+impl HostPattern {
+    /// Returns a new `HostPattern` from the given pattern.
+    pub fn new(pattern: &str) -> HostPattern {
+        let pattern = normalize(pattern.trim()).to_ascii_lowercase();
+        let kind = if pattern == "*" {
+            Kind::Any
+        } else if pattern.starts_with("*.") {
+            Kind::Suffix(pattern[1..].to_string())
+        } else {
+            Kind::Exact(pattern)
+        };
+        HostPattern { kind }
+    }
+
+    /// Returns true if `host` matches this pattern.
+    pub fn matches(&self, host: &str) -> bool {
+        let host = normalize(host);
+        match &self.kind {
+            Kind::Any => true,
+            Kind::Exact(exact) => host.eq_ignore_ascii_case(exact),
+            Kind::Suffix(suffix) => {
+                host.len() > suffix.len()
+                    && host.is_char_boundary(host.len() - suffix.len())
+                    && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            }
+        }
+    }
+}
+
+/// Strips the brackets around an IPv6 address and a trailing dot from `host`.
+fn normalize(host: &str) -> &str {
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+}
+
+impl From<&str> for HostPattern {
+    fn from(pattern: &str) -> HostPattern {
+        HostPattern::new(pattern)
+    }
+}
+
+impl From<String> for HostPattern {
+    fn from(pattern: String) -> HostPattern {
+        HostPattern::new(&pattern)
+    }
+}
+
+/// Lists of allowed and denied hosts.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HostFilter {
+    pub(crate) allow: Vec<HostPattern>,
+    pub(crate) deny: Vec<HostPattern>,
+}
+
+impl HostFilter {
+    /// Returns true if a connection to `host` is allowed.
+    ///
+    /// A host is allowed if it matches no denied pattern and, when there are allowed patterns, matches one of them.
+    pub(crate) fn is_allowed(&self, host: Option<&str>) -> bool {
+        let host = match host {
+            Some(host) => host,
+            None => return self.allow.is_empty(),
+        };
+
+        if self.deny.iter().any(|pattern| pattern.matches(host)) {
+            return false;
+        }
+
+        self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(host))
+    }
+}
+
+/// An error returned when connecting to a host which is not allowed.
+#[derive(Debug, Clone)]
+pub struct DeniedHost {
+    host: String,
+    redact: bool,
+}
+
+impl DeniedHost {
+    pub(crate) fn new(host: Option<&str>) -> DeniedHost {
+        DeniedHost {
+            host: host.unwrap_or_default().to_string(),
+            redact: false,
+        }
+    }
+
+    pub(crate) fn redact(mut self, redact: bool) -> DeniedHost {
+        self.redact = redact;
+        self
+    }
+
+    /// Returns the host which was denied.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+}
+
+impl fmt::Display for DeniedHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redact {
+            f.write_str("connection to host is not allowed")
+        } else {
+            write!(f, "connection to host `{}` is not allowed", self.host)
+        }
+    }
+}
+
+impl Error for DeniedHost {}
+
+/// Returns true if `ip` is a private, loopback, link-local or otherwise non-public address.
+///
+/// Link-local covers the `169.254.169.254` metadata endpoint used by cloud providers. Multicast and reserved addresses
+/// are included, as are IPv6 addresses which embed an IPv4 address (IPv4-mapped, IPv4-compatible, NAT64 and 6to4)
+/// when the embedded address is private.
+pub(crate) fn is_private_addr(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // "this network", 0.0.0.0/8
+                || a == 0
+                // shared address space, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+                // reserved, 240.0.0.0/4, which includes broadcast
+                || a >= 240
+        }
+        IpAddr::V6(ip) => {
+            if ip.is_loopback() || ip.is_unspecified() {
+                return true;
+            }
+            let segments = ip.segments();
+            let embedded = |hi: u16, lo: u16| {
+                let [a, b] = hi.to_be_bytes();
+                let [c, d] = lo.to_be_bytes();
+                is_private_addr(IpAddr::V4(Ipv4Addr::new(a, b, c, d)))
+            };
+            match segments {
+                // IPv4-mapped, ::ffff:0:0/96, and IPv4-compatible, ::/96
+                [0, 0, 0, 0, 0, 0xffff, hi, lo] | [0, 0, 0, 0, 0, 0, hi, lo] => embedded(hi, lo),
+                // NAT64 well-known prefix, 64:ff9b::/96
+                [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => embedded(hi, lo),
+                // NAT64 local-use prefix, 64:ff9b:1::/48, translates to addresses chosen by the network
+                [0x64, 0xff9b, 1, ..] => true,
+                // 6to4, 2002::/16
+                [0x2002, hi, lo, ..] => embedded(hi, lo),
+                [first, ..] => {
+                    ip.is_multicast()
+                        // unique local, fc00::/7
+                        || (first & 0xfe00) == 0xfc00
+                        // link-local, fe80::/10
+                        || (first & 0xffc0) == 0xfe80
+                }
+            }
+        }
+    }
+}
+
+/// An error returned when a connection was made to an address which is not allowed.
+#[derive(Debug, Clone)]
+pub struct DeniedAddress {
+    addr: Option<SocketAddr>,
+    redact: bool,
+}
+
+impl DeniedAddress {
+    pub(crate) fn new(addr: Option<SocketAddr>) -> DeniedAddress {
+        DeniedAddress {
+            addr,
+            redact: false,
+        }
+    }
+
+    pub(crate) fn redact(mut self, redact: bool) -> DeniedAddress {
+        self.redact = redact;
+        self
+    }
+
+    /// Returns the remote address which was denied.
+    ///
+    /// This is `None` if the inner connector did not report the remote address.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+}
+
+impl fmt::Display for DeniedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            Some(_) if self.redact => f.write_str("connection to private address is not allowed"),
+            Some(addr) => write!(f, "connection to private address `{}` is not allowed", addr),
+            None => f.write_str("connection to unknown remote address is not allowed"),
+        }
+    }
+}
+
+impl Error for DeniedAddress {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(uri: &str) -> HostKey {
+        HostKey::from_uri(&uri.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn host_key() {
+        assert_eq!(key("http://Example.com:80"), key("http://example.com"));
+        assert_eq!(
+            key("https://example.com.:443/path"),
+            key("https://example.com")
+        );
+        assert_ne!(key("http://example.com:8080"), key("http://example.com"));
+        assert_ne!(
+            key("http://example.com:443"),
+            key("https://example.com:443")
+        );
+
+        assert_eq!(key("http://Example.com:80").to_string(), "example.com");
+        assert_eq!(
+            key("http://example.com:8080").to_string(),
+            "example.com:8080"
+        );
+        assert_eq!(key("http://[::1]:8080").host(), "::1");
+        assert_eq!(key("http://[::1]:8080").to_string(), "[::1]:8080");
+
+        assert!(HostKey::from_uri(&"/path".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn pattern_matches() {
+        assert!(HostPattern::new("*").matches("example.com"));
+
+        let exact = HostPattern::new("Example.com");
+        assert!(exact.matches("example.com"));
+        assert!(exact.matches("EXAMPLE.COM"));
+        assert!(!exact.matches("www.example.com"));
+
+        let suffix = HostPattern::new("*.example.com");
+        assert!(suffix.matches("www.example.com"));
+        assert!(suffix.matches("a.b.example.com"));
+        assert!(!suffix.matches("example.com"));
+        assert!(!suffix.matches("badexample.com"));
+        assert!(suffix.matches("WWW.Example.com."));
+        assert!(HostPattern::new("*.Example.COM.").matches("www.example.com"));
+
+        // only `*.` is a wildcard
+        let bare = HostPattern::new("*example.com");
+        assert!(!bare.matches("badexample.com"));
+        assert!(!bare.matches("www.example.com"));
+
+        assert!(HostPattern::new("::1").matches("[::1]"));
+        assert!(HostPattern::new("example.com.").matches("example.com"));
+    }
+
+    #[test]
+    fn filter() {
+        let mut filter = HostFilter::default();
+        assert!(filter.is_allowed(Some("example.com")));
+        assert!(filter.is_allowed(None));
+
+        filter.deny.push("*.internal".into());
+        assert!(!filter.is_allowed(Some("db.internal")));
+        assert!(filter.is_allowed(Some("example.com")));
+
+        filter.allow.push("*.com".into());
+        assert!(filter.is_allowed(Some("example.com")));
+        assert!(!filter.is_allowed(Some("example.org")));
+        assert!(!filter.is_allowed(None));
+    }
+
+    #[test]
+    fn private_addr() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "255.255.255.255",
+            "0.1.2.3",
+            "ff02::1",
+            "::ffff:169.254.169.254",
+            "::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::10.0.0.1",
+            "64:ff9b:1::93.184.216.34",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(is_private_addr(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "93.184.216.34",
+            "100.128.0.1",
+            "223.255.255.255",
+            "2606:2800:220:1::1",
+            "::ffff:93.184.216.34",
+            "64:ff9b::93.184.216.34",
+            "2002:5db8:d822::1",
+        ] {
+            assert!(!is_private_addr(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn redact() {
+        let denied = DeniedHost::new(Some("db.internal"));
+        assert!(denied.to_string().contains("db.internal"));
+        let denied = denied.redact(true);
+        assert!(!denied.to_string().contains("db.internal"));
+        assert_eq!(denied.host(), "db.internal");
+
+        let addr = "10.0.0.1:80".parse().unwrap();
+        let denied = DeniedAddress::new(Some(addr)).redact(true);
+        assert!(!denied.to_string().contains("10.0.0.1"));
+        assert_eq!(denied.addr(), Some(addr));
+    }
+}
@@ -1,34 +1,162 @@
+//! A connect, read and write timeout aware connector to be used with hyper `Client`.
+//!
+//! # Testing with paused time
+//!
+//! All timeouts use the tokio clock, so tests can pause time instead of waiting for real timeouts to expire. When
+//! time is paused and the runtime has nothing else to do, it skips ahead to the next deadline. Advancing past several
+//! deadlines at once with `tokio::time::advance` fires every timeout which has passed.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use http_body_util::Empty;
+//! use hyper::body::Bytes;
+//! use hyper_timeout::TimeoutConnector;
+//! use hyper_util::client::legacy::{connect::HttpConnector, Client};
+//! use hyper_util::rt::TokioExecutor;
+//!
+//! # tokio::runtime::Builder::new_current_thread()
+//! #     .enable_all()
+//! #     .start_paused(true)
+//! #     .build()
+//! #     .unwrap()
+//! #     .block_on(async {
+//! // a server which accepts connections but never responds
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+//! let addr = listener.local_addr().unwrap();
+//! tokio::spawn(async move {
+//!     let mut held = Vec::new();
+//!     while let Ok((stream, _)) = listener.accept().await {
+//!         held.push(stream);
+//!     }
+//! });
+//!
+//! let mut connector = TimeoutConnector::new(HttpConnector::new());
+//! connector.set_read_timeout(Some(Duration::from_secs(30)));
+//! let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+//!
+//! // the 30 second read timeout fires without the test waiting 30 seconds
+//! let start = tokio::time::Instant::now();
+//! let res = client.get(format!("http://{}", addr).parse().unwrap()).await;
+//! assert!(res.is_err());
+//! assert!(start.elapsed() >= Duration::from_secs(30));
+//! # });
+//! ```
+
 use std::future::Future;
-use std::io;
+use std::net::{IpAddr, SocketAddr};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
 use hyper::rt::{Read, Write};
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout, Sleep};
 
+use hyper::body::Body;
+use hyper::http::Extensions;
 use hyper::Uri;
-use hyper_util::client::legacy::connect::{Connected, Connection};
+use hyper_util::client::legacy::connect::{Connect, Connected, Connection, HttpInfo};
+use hyper_util::client::legacy::{Builder, Client};
+use hyper_util::rt::TokioTimer;
 use tower_service::Service;
 
+mod body;
+mod budget;
+mod builder;
+mod connect_log;
+mod deadline;
+mod duration;
+mod error;
+mod host;
+pub mod prelude;
+mod resolver;
+mod stall;
 mod stream;
-use stream::TimeoutStream;
+mod timeout_core;
+mod timeouts;
+pub use body::{Checkpoint, TimeoutBody};
+pub use budget::Budget;
+pub use builder::{ConfigError, TimeoutConnectorBuilder, MAX_TIMEOUT};
+use connect_log::ConnectLog;
+pub use deadline::{current_deadline, with_deadline};
+pub use duration::{parse_duration, ParseDurationError};
+use error::ErrorMapper;
+pub use error::{is_timeout, timeout_phase, timeout_response, Phase, TimeoutError, TimeoutKind};
+use host::{is_private_addr, HostFilter};
+pub use host::{DeniedAddress, DeniedHost, HostKey, HostPattern};
+use resolver::with_dns_timeout;
+pub use resolver::{PublicResolver, TimeoutResolver};
+pub use stall::StallDetector;
+pub use stream::{TimeoutReader, TimeoutStream, TimeoutWriter};
+pub use timeout_core::{Action, TimeoutCore};
+use timeouts::Policy;
+pub use timeouts::{TimeoutPolicy, Timeouts};
 
 type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
+/// A pinned, boxed [`TimeoutStream`], as returned by [`TimeoutConnector`].
+///
+/// This implements hyper's `Read` and `Write` traits as well as `Connection`, so it can be used wherever the inner
+/// stream could.
+pub type BoxTimeoutStream<S> = Pin<Box<TimeoutStream<S>>>;
+
 /// A connector that enforces a connection timeout
 #[derive(Debug, Clone)]
 pub struct TimeoutConnector<T> {
     /// A connector implementing the `Connect` trait
     connector: T,
+    /// Amount of time to wait for the connector to be ready
+    ready_timeout: Option<Duration>,
+    /// Timer for the pending readiness check
+    ready_timer: ReadyTimer,
+    /// Amount of time to wait resolving the host name
+    dns_timeout: Option<Duration>,
     /// Amount of time to wait connecting
     connect_timeout: Option<Duration>,
+    /// Instant after which connecting fails
+    connect_deadline: Option<tokio::time::Instant>,
+    /// Amount of time to wait connecting to a loopback address
+    loopback_connect_timeout: Option<Duration>,
     /// Amount of time to wait reading response
     read_timeout: Option<Duration>,
     /// Amount of time to wait writing request
     write_timeout: Option<Duration>,
+    /// Amount of time to wait for the first byte of a response
+    first_byte_timeout: Option<Duration>,
+    /// Amount of time to wait for a whole request to be written
+    upload_timeout: Option<Duration>,
+    /// Amount of time to wait for a whole response to be read
+    download_timeout: Option<Duration>,
+    /// Detects a response being read too slowly
+    stall_detector: Option<StallDetector>,
+    /// Amount of time to wait without reading or writing
+    idle_timeout: Option<Duration>,
+    /// Amount of time the stream may go without progress, polled or not
+    max_silence: Option<Duration>,
+    /// Amount of time to wait shutting down the stream
+    shutdown_timeout: Option<Duration>,
+    /// Amount of time from connecting until the stream is closed
+    total_timeout: Option<Duration>,
     /// If true, resets the reader timeout whenever a write occures
     reset_reader_on_write: bool,
+    /// Maximum number of bytes passed to a single write on the stream
+    max_write_size: Option<usize>,
+    /// Chooses timeouts overriding all others for each destination
+    policy: Option<Policy>,
+    /// Timeouts overriding the defaults for matching hosts
+    host_timeouts: Vec<(HostPattern, Timeouts)>,
+    /// Timeouts overriding the defaults for a scheme
+    scheme_timeouts: Vec<(String, Timeouts)>,
+    /// Hosts which may or may not be connected to
+    host_filter: HostFilter,
+    /// If true, rejects connections to private remote addresses
+    deny_private_addrs: bool,
+    /// If true, leaves hosts and addresses out of error messages
+    redact_errors: bool,
+    /// Sink receiving a line for every connection attempt
+    connect_log: Option<ConnectLog>,
+    /// Converts the connector's own timeouts into application errors
+    error_mapper: Option<ErrorMapper>,
 }
 
 impl<T> TimeoutConnector<T>
@@ -39,15 +167,46 @@ where
     T::Error: Into<BoxError>,
 {
     /// Construct a new TimeoutConnector with a given connector implementing the `Connect` trait
+    ///
+    /// No timeouts are set initially. Until one is set, connecting does not arm a timer and the returned stream
+    /// passes every read and write straight through to the inner stream, so libraries can expose a
+    /// `TimeoutConnector` in their types while letting callers opt out of timeouts entirely.
     pub fn new(connector: T) -> Self {
         TimeoutConnector {
             connector,
+            ready_timeout: None,
+            ready_timer: ReadyTimer::default(),
+            dns_timeout: None,
             connect_timeout: None,
+            connect_deadline: None,
+            loopback_connect_timeout: None,
             read_timeout: None,
             write_timeout: None,
+            first_byte_timeout: None,
+            upload_timeout: None,
+            download_timeout: None,
+            stall_detector: None,
+            idle_timeout: None,
+            max_silence: None,
+            shutdown_timeout: None,
+            total_timeout: None,
             reset_reader_on_write: false,
+            max_write_size: None,
+            policy: None,
+            host_timeouts: Vec::new(),
+            scheme_timeouts: Vec::new(),
+            host_filter: HostFilter::default(),
+            deny_private_addrs: false,
+            redact_errors: false,
+            connect_log: None,
+            error_mapper: None,
         }
     }
+
+    /// Returns a builder for a TimeoutConnector wrapping the given connector
+    pub fn builder(connector: T) -> TimeoutConnectorBuilder<T> {
+        TimeoutConnectorBuilder::new(TimeoutConnector::new(connector))
+    }
 }
 
 impl<T> Service<Uri> for TimeoutConnector<T>
@@ -57,40 +216,138 @@ where
     T::Future: Send + 'static,
     T::Error: Into<BoxError>,
 {
-    type Response = Pin<Box<TimeoutStream<T::Response>>>;
+    type Response = BoxTimeoutStream<T::Response>;
     type Error = BoxError;
     #[allow(clippy::type_complexity)]
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.connector.poll_ready(cx).map_err(Into::into)
+        if let Poll::Ready(ready) = self.connector.poll_ready(cx) {
+            self.ready_timer.0 = None;
+            return Poll::Ready(ready.map_err(Into::into));
+        }
+
+        let ready_timeout = match self.ready_timeout {
+            Some(ready_timeout) => ready_timeout,
+            None => return Poll::Pending,
+        };
+        let timer = self
+            .ready_timer
+            .0
+            .get_or_insert_with(|| Box::pin(sleep(ready_timeout)));
+        match timer.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.ready_timer.0 = None;
+                let err = TimeoutError::new(Phase::Ready).with_timeout(Some(ready_timeout));
+                Poll::Ready(Err(ErrorMapper::map(self.error_mapper.as_ref(), err)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
     }
 
     fn call(&mut self, dst: Uri) -> Self::Future {
-        let connect_timeout = self.connect_timeout;
-        let read_timeout = self.read_timeout;
-        let write_timeout = self.write_timeout;
+        let key = HostKey::from_uri(&dst);
+        let host = key.as_ref().map(HostKey::host);
+        if !self.host_filter.is_allowed(host) {
+            let err: BoxError = Box::new(DeniedHost::new(host).redact(self.redact_errors));
+            return Box::pin(async move { Err(err) });
+        }
+        if self.deny_private_addrs {
+            // an address literal is refused without connecting, resolved names are only checked once connected
+            if let Some(ip) = host.and_then(|host| host.parse::<IpAddr>().ok()) {
+                if is_private_addr(ip) {
+                    let port = dst.port_u16().unwrap_or(match dst.scheme_str() {
+                        Some("https") => 443,
+                        _ => 80,
+                    });
+                    let denied = DeniedAddress::new(Some(SocketAddr::new(ip, port)));
+                    let err: BoxError = Box::new(denied.redact(self.redact_errors));
+                    return Box::pin(async move { Err(err) });
+                }
+            }
+        }
+
+        let timeouts = self.timeouts_for(&dst, host);
+        // connecting may not take longer than the whole deadline, nor go past the connect or task deadline
+        let connect = cap_timeout(
+            (timeouts.connect, Phase::Connect),
+            self.total_timeout.map(Budget::new),
+        );
+        let connect_deadline = match (self.connect_deadline, current_deadline()) {
+            (Some(deadline), Some(task)) => Some(deadline.min(task)),
+            (deadline, task) => deadline.or(task),
+        };
+        let (connect_timeout, connect_phase) =
+            cap_timeout(connect, connect_deadline.map(Budget::until));
+        let started = tokio::time::Instant::now();
+        let deadline = self
+            .total_timeout
+            .and_then(|total| started.checked_add(total));
+        let read_timeout = timeouts.read;
+        let write_timeout = timeouts.write;
+        let first_byte_timeout = self.first_byte_timeout;
+        let upload_timeout = self.upload_timeout;
+        let download_timeout = self.download_timeout;
+        let stall_detector = self.stall_detector.clone();
+        let idle_timeout = self.idle_timeout;
+        let max_silence = self.max_silence;
+        let shutdown_timeout = self.shutdown_timeout;
         let reset_reader_on_write = self.reset_reader_on_write;
-        let connecting = self.connector.call(dst);
+        let max_write_size = self.max_write_size;
+        let deny_private_addrs = self.deny_private_addrs;
+        let redact_errors = self.redact_errors;
+        let error_host = host.filter(|_| !redact_errors).map(str::to_owned);
+        let connect_log = self
+            .connect_log
+            .clone()
+            .map(|log| (log, error_host.clone()));
+        let error_mapper = self.error_mapper.clone();
+        let connecting = with_dns_timeout(self.dns_timeout, self.connector.call(dst));
 
         let fut = async move {
-            let mut stream = match connect_timeout {
-                None => {
-                    let io = connecting.await.map_err(Into::into)?;
-                    TimeoutStream::new(io)
-                }
-                Some(connect_timeout) => {
-                    let timeout = timeout(connect_timeout, connecting);
-                    let connecting = timeout
-                        .await
-                        .map_err(|e| io::Error::new(io::ErrorKind::TimedOut, e))?;
-                    let io = connecting.map_err(Into::into)?;
-                    TimeoutStream::new(io)
+            let start = tokio::time::Instant::now();
+            let connected = async {
+                let io = match connect_timeout {
+                    None => connecting.await.map_err(Into::into)?,
+                    Some(connect_timeout) => {
+                        let timeout = timeout(connect_timeout, connecting);
+                        let connecting = timeout.await.map_err(|_| {
+                            // a deadline has no configured duration, only the time which was left of it
+                            let configured =
+                                Some(connect_timeout).filter(|_| connect_phase == Phase::Connect);
+                            let err = TimeoutError::new(connect_phase)
+                                .with_host(error_host.as_deref())
+                                .with_timeout(configured)
+                                .with_elapsed(start.elapsed());
+                            ErrorMapper::map(error_mapper.as_ref(), err)
+                        })?;
+                        connecting.map_err(Into::into)?
+                    }
+                };
+                if deny_private_addrs {
+                    check_remote_addr(&io).map_err(|e| e.redact(redact_errors))?;
                 }
-            };
+                Ok::<_, BoxError>(io)
+            }
+            .await;
+            if let Some((log, host)) = &connect_log {
+                log.record(host.as_deref(), start.elapsed(), connected.as_ref().err());
+            }
+            let io = connected?;
+            let mut stream = TimeoutStream::new(io);
             stream.set_read_timeout(read_timeout);
             stream.set_write_timeout(write_timeout);
+            stream.set_first_byte_timeout(first_byte_timeout);
+            stream.set_upload_timeout(upload_timeout);
+            stream.set_download_timeout(download_timeout);
+            stream.set_stall_detector(stall_detector);
+            stream.set_idle_timeout(idle_timeout);
+            stream.set_max_silence(max_silence);
+            stream.set_shutdown_timeout(shutdown_timeout);
             stream.set_reset_reader_on_write(reset_reader_on_write);
+            stream.set_max_write_size(max_write_size);
+            stream.set_deadline(deadline, started);
+            stream.set_error_host(error_host.as_deref());
             Ok(Box::pin(stream))
         };
 
@@ -99,14 +356,83 @@ where
 }
 
 impl<T> TimeoutConnector<T> {
+    /// Set the timeout for the inner connector to become ready.
+    ///
+    /// Connectors which manage their own pools may stay not ready in `poll_ready` indefinitely, holding up requests
+    /// before connecting has even started. When set, `poll_ready` fails with a
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) error once the inner connector has not been ready for this long.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_ready_timeout(&mut self, val: Option<Duration>) {
+        self.ready_timeout = val;
+        self.ready_timer = ReadyTimer::default();
+    }
+
+    /// Set the timeout for resolving the host name.
+    ///
+    /// The connector does not resolve names itself, so this only applies when the inner connector resolves through a
+    /// [`TimeoutResolver`] which has no timeout of its own. Resolution which takes too long then fails with a
+    /// [`Phase::Dns`] error, reported separately from a connect timeout. The connect timeout still bounds resolving
+    /// and connecting together.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use hyper_timeout::{TimeoutConnector, TimeoutResolver};
+    /// use hyper_util::client::legacy::connect::{dns::GaiResolver, HttpConnector};
+    ///
+    /// let http = HttpConnector::new_with_resolver(TimeoutResolver::new(GaiResolver::new()));
+    /// let mut connector = TimeoutConnector::new(http);
+    /// connector.set_dns_timeout(Some(Duration::from_secs(2)));
+    /// connector.set_connect_timeout(Some(Duration::from_secs(5)));
+    /// ```
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_dns_timeout(&mut self, val: Option<Duration>) {
+        self.dns_timeout = val;
+    }
+
     /// Set the timeout for connecting to a URL.
     ///
+    /// A connect made inside [`with_deadline`] ends at the task's deadline instead when that comes first.
+    ///
     /// Default is no timeout.
     #[inline]
     pub fn set_connect_timeout(&mut self, val: Option<Duration>) {
         self.connect_timeout = val;
     }
 
+    /// Set an instant after which connecting fails.
+    ///
+    /// This is for callers which have already computed an absolute deadline, so it does not need to be converted
+    /// back to a duration. Every connect ends at the deadline if that comes before the connect timeout, failing with
+    /// a [`Phase::Deadline`] error, and connects started after it fail immediately. The deadline is measured on the
+    /// tokio clock. For a deadline which applies to a single request, use [`with_deadline`] instead.
+    ///
+    /// Default is no deadline.
+    #[inline]
+    pub fn set_connect_deadline(&mut self, val: Option<tokio::time::Instant>) {
+        self.connect_deadline = val;
+    }
+
+    /// Set the timeout for connecting to a loopback destination.
+    ///
+    /// When set, this is used instead of the connect timeout if the destination host is `localhost` or a loopback IP
+    /// address. Local connects either succeed or fail almost immediately, so a much smaller timeout avoids hiding
+    /// local failures behind a timeout meant for remote hosts.
+    ///
+    /// This takes the place of the connect timeout only, so a connect timeout from the
+    /// [timeout policy](Self::set_timeout_policy), a [host rule](Self::set_host_timeouts) or a
+    /// [scheme rule](Self::set_scheme_timeouts) matching the destination still takes precedence over it.
+    ///
+    /// Default is to use the connect timeout.
+    #[inline]
+    pub fn set_loopback_connect_timeout(&mut self, val: Option<Duration>) {
+        self.loopback_connect_timeout = val;
+    }
+
     /// Set the timeout for the response.
     ///
     /// Default is no timeout.
@@ -123,6 +449,113 @@ impl<T> TimeoutConnector<T> {
         self.write_timeout = val;
     }
 
+    /// Set the timeout between writing a request and the first byte of the response.
+    ///
+    /// The countdown restarts with every write, so it covers the time the server takes to start responding after the
+    /// request has been sent. Once the response has started, only the read timeout applies to it.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_first_byte_timeout(&mut self, val: Option<Duration>) {
+        self.first_byte_timeout = val;
+    }
+
+    /// Set the timeout for uploading a request as a whole.
+    ///
+    /// The upload starts with the first write of a request and lasts until the response starts to arrive, however
+    /// often individual writes complete.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_upload_timeout(&mut self, val: Option<Duration>) {
+        self.upload_timeout = val;
+    }
+
+    /// Set the timeout for downloading a response as a whole.
+    ///
+    /// The download starts when the response starts to arrive and lasts until the next request is written, however
+    /// often individual reads complete. The stream cannot tell where a response ends, so a pooled connection which
+    /// stays idle for longer than this timeout is closed instead of being reused.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_download_timeout(&mut self, val: Option<Duration>) {
+        self.download_timeout = val;
+    }
+
+    /// Set a stall detector for downloads, failing a response which is read too slowly.
+    ///
+    /// Each connection gets its own copy of the detector, which is reset when a response starts to arrive and fed the
+    /// bytes of every read, so the connector applies the same check as a [`StallDetector`] used directly. A read
+    /// which stays pending is not measured; use a read or idle timeout to catch a response which stops altogether.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use hyper_timeout::{StallDetector, TimeoutConnector};
+    /// use hyper_util::client::legacy::connect::HttpConnector;
+    ///
+    /// let mut connector = TimeoutConnector::new(HttpConnector::new());
+    /// // at least 64 KiB every 10 seconds
+    /// connector.set_stall_detector(Some(StallDetector::new(Duration::from_secs(10), 64 * 1024)));
+    /// connector.set_read_timeout(Some(Duration::from_secs(10)));
+    /// ```
+    ///
+    /// Default is no stall detector.
+    #[inline]
+    pub fn set_stall_detector(&mut self, val: Option<StallDetector>) {
+        self.stall_detector = val;
+    }
+
+    /// Set the timeout for the stream making no progress.
+    ///
+    /// Unlike the read and write timeouts, this only expires when neither reads nor writes have completed for the
+    /// whole timeout, so long responses which arrive in slow bursts are not interrupted.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_idle_timeout(&mut self, val: Option<Duration>) {
+        self.idle_timeout = val;
+    }
+
+    /// Set the maximum time the stream may go without progress in either direction.
+    ///
+    /// Unlike the idle timeout, this counts whether or not an operation is pending, so it also catches a connection
+    /// which is held without being polled. The countdown starts when the connection is made.
+    ///
+    /// Default is no limit.
+    #[inline]
+    pub fn set_max_silence(&mut self, val: Option<Duration>) {
+        self.max_silence = val;
+    }
+
+    /// Set the timeout for shutting down the stream.
+    ///
+    /// This bounds closing the connection, including any TLS `close_notify` sent by the inner stream, so a dead peer
+    /// cannot hold up the shutdown forever. When it expires, shutting down fails and the stream can be dropped.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_shutdown_timeout(&mut self, val: Option<Duration>) {
+        self.shutdown_timeout = val;
+    }
+
+    /// Set the overall timeout, from starting to connect until the stream is closed.
+    ///
+    /// This bounds everything the inner connector does to connect, including DNS resolution and any TLS handshake,
+    /// followed by all reading and writing on the resulting stream, however active it is. Once it expires, connecting
+    /// fails or every further operation on the stream fails with a [`TimedOut`](std::io::ErrorKind::TimedOut) error.
+    /// The connect timeout is capped to this value.
+    ///
+    /// The deadline belongs to the connection rather than to a request. `Client` reuses pooled connections for later
+    /// requests, so to bound each request on its own, disable pooling with `pool_max_idle_per_host(0)`.
+    ///
+    /// Default is no timeout.
+    #[inline]
+    pub fn set_total_timeout(&mut self, val: Option<Duration>) {
+        self.total_timeout = val;
+    }
+
     /// Reset on the reader timeout on write
     ///
     /// This will reset the reader timeout when a write is done through the
@@ -131,6 +564,295 @@ impl<T> TimeoutConnector<T> {
     pub fn set_reset_reader_on_write(&mut self, reset: bool) {
         self.reset_reader_on_write = reset;
     }
+
+    /// Set a policy choosing the timeouts for each destination.
+    ///
+    /// The policy is consulted on every call. The timeouts it returns take precedence over those set with
+    /// [`set_host_timeouts`](Self::set_host_timeouts) and [`set_scheme_timeouts`](Self::set_scheme_timeouts), which in
+    /// turn take precedence over the connector's own connect, read and write timeouts.
+    ///
+    /// Default is no policy.
+    pub fn set_timeout_policy(&mut self, policy: impl TimeoutPolicy + 'static) {
+        self.policy = Some(Policy::new(policy));
+    }
+
+    /// Set a function choosing the timeouts for each destination.
+    ///
+    /// This is a shorthand for [`set_timeout_policy`](Self::set_timeout_policy) with a closure, and replaces any
+    /// policy which was set before.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use hyper_timeout::{TimeoutConnector, Timeouts};
+    /// use hyper_util::client::legacy::connect::HttpConnector;
+    ///
+    /// let mut connector = TimeoutConnector::new(HttpConnector::new());
+    /// connector.set_timeout_fn(|dst| match dst.path() {
+    ///     "/export" => Timeouts {
+    ///         read: Some(Duration::from_secs(300)),
+    ///         ..Timeouts::default()
+    ///     },
+    ///     _ => Timeouts::default(),
+    /// });
+    /// ```
+    pub fn set_timeout_fn<F>(&mut self, f: F)
+    where
+        F: Fn(&Uri) -> Timeouts + Send + Sync + 'static,
+    {
+        self.set_timeout_policy(f);
+    }
+
+    /// Set timeouts for connections to hosts matching `pattern`.
+    ///
+    /// The timeouts which are set override the connector's own connect, read and write timeouts for these hosts; the
+    /// others fall back to the connector's. When several patterns match a host, the one which was added first is
+    /// used. Setting timeouts for a pattern which already has some replaces them.
+    ///
+    /// Default is to use the same timeouts for every host.
+    pub fn set_host_timeouts(&mut self, pattern: impl Into<HostPattern>, timeouts: Timeouts) {
+        let pattern = pattern.into();
+        match self.host_timeouts.iter_mut().find(|(p, _)| *p == pattern) {
+            Some((_, existing)) => *existing = timeouts,
+            None => self.host_timeouts.push((pattern, timeouts)),
+        }
+    }
+
+    /// Returns the connect, read and write timeouts for `dst`, whose normalized host is `host`.
+    fn timeouts_for(&self, dst: &Uri, host: Option<&str>) -> Timeouts {
+        let connect = match self.loopback_connect_timeout {
+            Some(timeout) if is_loopback(dst) => Some(timeout),
+            _ => self.connect_timeout,
+        };
+        let policy = match &self.policy {
+            Some(policy) => policy.timeouts_for(dst),
+            None => Timeouts::default(),
+        };
+        policy
+            .or(self.host_timeouts(host))
+            .or(self.scheme_timeouts(dst.scheme_str()))
+            .or(Timeouts {
+                connect,
+                read: self.read_timeout,
+                write: self.write_timeout,
+            })
+    }
+
+    /// Returns the timeouts set for `host`, if any.
+    fn host_timeouts(&self, host: Option<&str>) -> Timeouts {
+        host.and_then(|host| {
+            self.host_timeouts
+                .iter()
+                .find(|(pattern, _)| pattern.matches(host))
+        })
+        .map(|(_, timeouts)| *timeouts)
+        .unwrap_or_default()
+    }
+
+    /// Set timeouts for connections to destinations with the given scheme, such as `https`.
+    ///
+    /// The timeouts which are set override the connector's own connect, read and write timeouts for this scheme; the
+    /// others fall back to the connector's. Timeouts set for a matching host with
+    /// [`set_host_timeouts`](Self::set_host_timeouts) take precedence. Schemes are compared ignoring ASCII case.
+    ///
+    /// Default is to use the same timeouts for every scheme.
+    pub fn set_scheme_timeouts(&mut self, scheme: &str, timeouts: Timeouts) {
+        let scheme = scheme.to_ascii_lowercase();
+        match self.scheme_timeouts.iter_mut().find(|(s, _)| *s == scheme) {
+            Some((_, existing)) => *existing = timeouts,
+            None => self.scheme_timeouts.push((scheme, timeouts)),
+        }
+    }
+
+    /// Returns the timeouts set for `scheme`, if any.
+    fn scheme_timeouts(&self, scheme: Option<&str>) -> Timeouts {
+        scheme
+            .and_then(|scheme| {
+                self.scheme_timeouts
+                    .iter()
+                    .find(|(s, _)| s.eq_ignore_ascii_case(scheme))
+            })
+            .map(|(_, timeouts)| *timeouts)
+            .unwrap_or_default()
+    }
+
+    /// Only allow connections to hosts matching one of the given patterns.
+    ///
+    /// Connections to any other host fail immediately with a [`DeniedHost`] error, before the inner connector is
+    /// called. An empty list allows every host.
+    ///
+    /// Default is to allow every host.
+    pub fn set_allowed_hosts<I>(&mut self, hosts: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<HostPattern>,
+    {
+        self.host_filter.allow = hosts.into_iter().map(Into::into).collect();
+    }
+
+    /// Deny connections to hosts matching any of the given patterns.
+    ///
+    /// Connections to these hosts fail immediately with a [`DeniedHost`] error, before the inner connector is called.
+    /// Denied hosts take precedence over allowed hosts.
+    ///
+    /// Default is to deny no hosts.
+    pub fn set_denied_hosts<I>(&mut self, hosts: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<HostPattern>,
+    {
+        self.host_filter.deny = hosts.into_iter().map(Into::into).collect();
+    }
+
+    /// Reject connections whose remote address is private.
+    ///
+    /// An address is private if it is private, loopback, link-local (which includes cloud metadata endpoints),
+    /// unspecified, multicast or reserved, or an IPv6 address embedding such an IPv4 address. Destinations given as a
+    /// private address are refused with a [`DeniedAddress`] error without connecting.
+    ///
+    /// Host names are resolved by the inner connector, which this connector cannot see into, so for them this is a
+    /// check after connecting: the remote address the inner connector reports is checked once it has connected, and
+    /// the connection is dropped with a [`DeniedAddress`] error if the address is private. By then the TCP connection
+    /// has been made, as has the TLS handshake when the inner connector is an HTTPS connector, so this alone does not
+    /// guard against server-side request forgery. Resolve through a [`PublicResolver`] for that, which filters private
+    /// addresses out before any connection is made, and keep this check as a backstop:
+    ///
+    /// ```
+    /// use hyper_timeout::{PublicResolver, TimeoutConnector};
+    /// use hyper_util::client::legacy::connect::{dns::GaiResolver, HttpConnector};
+    ///
+    /// let http = HttpConnector::new_with_resolver(PublicResolver::new(GaiResolver::new()));
+    /// let mut connector = TimeoutConnector::new(http);
+    /// connector.set_deny_private_addrs(true);
+    /// ```
+    ///
+    /// The remote address is read from the [`HttpInfo`] the inner connector attaches to the connection. Connections
+    /// which do not report one are rejected. When connecting through a proxy, the address checked is the proxy's.
+    ///
+    /// Default is false.
+    pub fn set_deny_private_addrs(&mut self, deny: bool) {
+        self.deny_private_addrs = deny;
+    }
+
+    /// Leave hosts and addresses out of the messages of errors returned by the connector.
+    ///
+    /// This is for environments where logs may not contain destination names. Only the `Display` output is affected;
+    /// [`DeniedHost::host`] and [`DeniedAddress::addr`] still return the host and address. Errors from the inner
+    /// connector are passed on as they are, so a [`PublicResolver`] needs its own
+    /// [`set_redact_errors`](PublicResolver::set_redact_errors).
+    ///
+    /// Default is false.
+    pub fn set_redact_errors(&mut self, redact: bool) {
+        self.redact_errors = redact;
+    }
+
+    /// Set a function converting the connector's timeouts into the application's own errors.
+    ///
+    /// The function is applied to the timeouts the connector reports itself, when the inner connector is not
+    /// [ready](Self::set_ready_timeout) in time and when connecting times out, and its result is returned as the
+    /// connector's error. Timeouts on the stream are reported from hyper's IO traits, which require an
+    /// [`io::Error`](std::io::Error), so they are not mapped.
+    ///
+    /// ```
+    /// use std::fmt;
+    ///
+    /// use hyper_timeout::{TimeoutConnector, TimeoutError};
+    /// use hyper_util::client::legacy::connect::HttpConnector;
+    ///
+    /// #[derive(Debug)]
+    /// struct UpstreamUnavailable(TimeoutError);
+    ///
+    /// impl fmt::Display for UpstreamUnavailable {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         write!(f, "upstream unavailable: {}", self.0)
+    ///     }
+    /// }
+    ///
+    /// impl std::error::Error for UpstreamUnavailable {}
+    ///
+    /// let mut connector = TimeoutConnector::new(HttpConnector::new());
+    /// connector.set_error_mapper(|e| Box::new(UpstreamUnavailable(e)));
+    /// ```
+    ///
+    /// Default is to return the timeout as an [`io::Error`](std::io::Error) of kind
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut).
+    pub fn set_error_mapper<F>(&mut self, f: F)
+    where
+        F: Fn(TimeoutError) -> BoxError + Send + Sync + 'static,
+    {
+        self.error_mapper = Some(ErrorMapper::new(f));
+    }
+
+    /// Write a line for every connection attempt to `sink`.
+    ///
+    /// Each line holds the Unix time the attempt ended, the destination host, how long it took and its outcome:
+    /// `connected`, `timeout` with the phase which timed out, or `failed`, followed by the error. This is meant for
+    /// debugging in the field without going through the application's logging. When errors are
+    /// [redacted](Self::set_redact_errors), the host is logged as `-`.
+    ///
+    /// Each line is written and flushed with a blocking call, from inside the future making the connection and
+    /// while holding a lock shared by every connection made through this connector and its clones. A sink which
+    /// blocks, such as a file on a slow disk or a pipe which is not being read, holds up the runtime thread and
+    /// every other connection attempt waiting to log. Use a sink which only hands the line off, for example to a
+    /// channel drained by a thread of its own, when that matters.
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    ///
+    /// use hyper_timeout::TimeoutConnector;
+    /// use hyper_util::client::legacy::connect::HttpConnector;
+    ///
+    /// let mut connector = TimeoutConnector::new(HttpConnector::new());
+    /// connector.set_connect_log(File::create("connects.log").unwrap());
+    /// ```
+    ///
+    /// Default is no log.
+    pub fn set_connect_log(&mut self, sink: impl std::io::Write + Send + 'static) {
+        self.connect_log = Some(ConnectLog::new(sink));
+    }
+
+    /// Set the maximum number of bytes passed to a single write on the stream.
+    ///
+    /// Large buffers are split into writes of at most this size, so the write timeout measures progress on each
+    /// piece. This keeps the write timeout meaningful when upper layers flush large buffered or compressed bodies.
+    ///
+    /// Default is no limit.
+    pub fn set_max_write_size(&mut self, size: Option<usize>) {
+        self.max_write_size = size;
+    }
+
+    /// Builds a `Client` using this connector, configuring the builder's connection pool to match it.
+    ///
+    /// The pool is given a tokio timer, so it runs on the same clock as the connector's timeouts, and when a
+    /// [maximum silence](Self::set_max_silence) is set, pooled connections are evicted once they have been idle that
+    /// long. Otherwise the pool could hand out a connection which has already gone silent for too long, and the first
+    /// request on it would fail. The rest of the builder's configuration is kept.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use http_body_util::Empty;
+    /// use hyper::body::Bytes;
+    /// use hyper_timeout::TimeoutConnector;
+    /// use hyper_util::client::legacy::{connect::HttpConnector, Client};
+    /// use hyper_util::rt::TokioExecutor;
+    ///
+    /// let mut connector = TimeoutConnector::new(HttpConnector::new());
+    /// connector.set_max_silence(Some(Duration::from_secs(60)));
+    /// let client = connector.install_into::<Empty<Bytes>>(&mut Client::builder(TokioExecutor::new()));
+    /// ```
+    pub fn install_into<B>(self, builder: &mut Builder) -> Client<Self, B>
+    where
+        Self: Connect + Clone,
+        B: Body + Send,
+        B::Data: Send,
+    {
+        builder.pool_timer(TokioTimer::new());
+        if let Some(max_silence) = self.max_silence {
+            builder.pool_idle_timeout(max_silence);
+        }
+        builder.build(self)
+    }
 }
 
 impl<T> Connection for TimeoutConnector<T>
@@ -145,19 +867,163 @@ where
     }
 }
 
+/// The timer for a pending `poll_ready`.
+///
+/// Each clone of a connector waits for readiness separately, so clones start without a timer.
+#[derive(Debug, Default)]
+struct ReadyTimer(Option<Pin<Box<Sleep>>>);
+
+impl Clone for ReadyTimer {
+    fn clone(&self) -> ReadyTimer {
+        ReadyTimer(None)
+    }
+}
+
+/// Connects to `dst` using `connector`, waiting for it to be ready first.
+///
+/// This is the equivalent of `tower::ServiceExt::oneshot` for a `TimeoutConnector`. As with any `Service`,
+/// `poll_ready` must return `Poll::Ready(Ok(()))` before `call` is used. `TimeoutConnector` forwards readiness to the
+/// inner connector and does not check it again in `call`, so code driving the connector by hand should use this
+/// helper or `ServiceExt::ready` rather than calling `call` directly.
+pub async fn oneshot_connect<T>(
+    mut connector: TimeoutConnector<T>,
+    dst: Uri,
+) -> Result<BoxTimeoutStream<T::Response>, BoxError>
+where
+    T: Service<Uri> + Send,
+    T::Response: Read + Write + Connection + Send + Unpin,
+    T::Future: Send + 'static,
+    T::Error: Into<BoxError>,
+{
+    std::future::poll_fn(|cx| connector.poll_ready(cx)).await?;
+    connector.call(dst).await
+}
+
+/// Awaits `fut`, failing with a [`TimeoutError`] for `phase` if it does not complete within `duration`.
+///
+/// This applies the crate's timeout error to work outside the connector, such as waiting for a response or reading a
+/// whole body, so every timeout can be handled the same way. The error converts into an [`io::Error`](std::io::Error)
+/// with a kind of [`TimedOut`](std::io::ErrorKind::TimedOut), like the errors of the connector and its streams.
+///
+/// ```
+/// # async fn run() {
+/// use std::time::Duration;
+///
+/// use hyper_timeout::{timeout_future, Phase};
+///
+/// let res = timeout_future(Phase::Custom("handshake"), Duration::from_secs(1), std::future::pending::<()>()).await;
+/// assert_eq!(res.unwrap_err().to_string(), "handshake timed out (timeout 1s)");
+/// # }
+/// ```
+pub async fn timeout_future<F: Future>(
+    phase: Phase,
+    duration: Duration,
+    fut: F,
+) -> Result<F::Output, TimeoutError> {
+    timeout(duration, fut)
+        .await
+        .map_err(|_| TimeoutError::new(phase).with_timeout(Some(duration)))
+}
+
+/// Returns a connect timeout capped by the time left in `budget`, along with the phase to report when it expires.
+fn cap_timeout(
+    timeout: (Option<Duration>, Phase),
+    budget: Option<Budget>,
+) -> (Option<Duration>, Phase) {
+    let budget = match budget {
+        Some(budget) => budget,
+        None => return timeout,
+    };
+    match timeout {
+        (Some(t), phase) if budget.cap(t) == t => (Some(t), phase),
+        _ => (Some(budget.remaining()), Phase::Deadline),
+    }
+}
+
+/// Returns an error if the remote address of `io` is private or unknown.
+fn check_remote_addr<C: Connection>(io: &C) -> Result<(), DeniedAddress> {
+    let mut extensions = Extensions::new();
+    io.connected().get_extras(&mut extensions);
+    match extensions.get::<HttpInfo>() {
+        Some(info) if !is_private_addr(info.remote_addr().ip()) => Ok(()),
+        info => Err(DeniedAddress::new(info.map(HttpInfo::remote_addr))),
+    }
+}
+
+/// Returns true if the destination is `localhost` or a loopback IP address.
+fn is_loopback(dst: &Uri) -> bool {
+    let key = match HostKey::from_uri(dst) {
+        Some(key) => key,
+        None => return false,
+    };
+
+    key.host() == "localhost" || matches!(key.host().parse::<IpAddr>(), Ok(ip) if ip.is_loopback())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
     use std::{error::Error, io};
 
     use http_body_util::Empty;
     use hyper::body::Bytes;
     use hyper_util::{
-        client::legacy::{connect::HttpConnector, Client},
+        client::legacy::{
+            connect::{
+                dns::{GaiResolver, Name},
+                HttpConnector,
+            },
+            Client,
+        },
         rt::TokioExecutor,
     };
 
-    use super::TimeoutConnector;
+    use std::future::{pending, Pending};
+    use std::task::{Context, Poll};
+
+    use hyper::Uri;
+    use tower_service::Service;
+
+    use super::{
+        is_loopback, is_timeout, oneshot_connect, timeout_future, timeout_phase, with_deadline,
+        DeniedAddress, DeniedHost, Phase, PublicResolver, TimeoutConnector, TimeoutError,
+        TimeoutPolicy, TimeoutResolver, Timeouts,
+    };
+
+    #[derive(Clone)]
+    struct NeverReady;
+
+    impl Service<Uri> for NeverReady {
+        type Response = <HttpConnector as Service<Uri>>::Response;
+        type Error = io::Error;
+        type Future = Pending<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Pending
+        }
+
+        fn call(&mut self, _dst: Uri) -> Self::Future {
+            pending()
+        }
+    }
+
+    #[derive(Clone)]
+    struct NeverConnects;
+
+    impl Service<Uri> for NeverConnects {
+        type Response = <HttpConnector as Service<Uri>>::Response;
+        type Error = io::Error;
+        type Future = Pending<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _dst: Uri) -> Self::Future {
+            pending()
+        }
+    }
 
     #[tokio::test]
     async fn test_timeout_connector() {
@@ -206,4 +1072,488 @@ mod tests {
         }
         panic!("Expected timeout error");
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_timeout_error() {
+        // a server which accepts connections but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: hyper::Uri = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        for redact in [false, true] {
+            let mut connector = TimeoutConnector::new(HttpConnector::new());
+            connector.set_read_timeout(Some(Duration::from_secs(30)));
+            connector.set_redact_errors(redact);
+            let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+
+            let client_e = client.get(url.clone()).await.unwrap_err();
+            let io_e = client_e.source().unwrap().source().unwrap();
+            let io_e = io_e.downcast_ref::<io::Error>().unwrap();
+            let timeout_e = io_e.get_ref().unwrap().downcast_ref::<TimeoutError>();
+            let timeout_e = timeout_e.unwrap();
+            assert_eq!(timeout_e.phase(), Phase::Read);
+            assert_eq!(timeout_e.timeout(), Some(Duration::from_secs(30)));
+            assert_eq!(timeout_e.elapsed(), Some(Duration::from_secs(30)));
+            if redact {
+                assert_eq!(timeout_e.host(), None);
+            } else {
+                assert_eq!(timeout_e.host(), Some("127.0.0.1"));
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_total_timeout() {
+        // a server which accepts connections but never responds
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let http = HttpConnector::new();
+        let mut connector = TimeoutConnector::new(http);
+        connector.set_total_timeout(Some(Duration::from_secs(5)));
+
+        let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+
+        let start = tokio::time::Instant::now();
+        let res = client.get(url).await;
+        assert!(start.elapsed() >= Duration::from_secs(5));
+
+        if let Err(client_e) = res {
+            assert!(is_timeout(&client_e));
+            assert_eq!(timeout_phase(&client_e), Some(Phase::Deadline));
+            if let Some(hyper_e) = client_e.source() {
+                if let Some(io_e) = hyper_e.source().unwrap().downcast_ref::<io::Error>() {
+                    assert_eq!(io_e.kind(), io::ErrorKind::TimedOut);
+                    return assert_eq!(
+                        io_e.to_string(),
+                        "deadline timed out for 127.0.0.1 after 5s"
+                    );
+                }
+            }
+        }
+        panic!("Expected timeout error");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout_future() {
+        let res = timeout_future(Phase::Body, Duration::from_secs(1), async { 1 }).await;
+        assert_eq!(res, Ok(1));
+
+        let err = timeout_future(
+            Phase::Body,
+            Duration::from_secs(1),
+            std::future::pending::<()>(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.phase(), Phase::Body);
+    }
+
+    #[tokio::test]
+    async fn test_oneshot_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+
+        let http = HttpConnector::new();
+        let mut connector = TimeoutConnector::new(http);
+        connector.set_connect_timeout(Some(Duration::from_secs(1)));
+
+        oneshot_connect(connector, url).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ready_timeout() {
+        let url = "http://example.com".parse().unwrap();
+
+        let mut connector = TimeoutConnector::new(NeverReady);
+        connector.set_ready_timeout(Some(Duration::from_secs(1)));
+
+        match oneshot_connect(connector, url).await {
+            Ok(_) => panic!("Expected a timeout"),
+            Err(e) => {
+                let io_e = e.downcast_ref::<io::Error>().unwrap();
+                assert_eq!(io_e.kind(), io::ErrorKind::TimedOut);
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_task_deadline() {
+        let url: Uri = "http://example.com".parse().unwrap();
+
+        let mut connector = TimeoutConnector::new(NeverConnects);
+        connector.set_connect_timeout(Some(Duration::from_secs(10)));
+
+        let start = tokio::time::Instant::now();
+        let deadline = start + Duration::from_secs(1);
+        let e = with_deadline(deadline, oneshot_connect(connector.clone(), url.clone()))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(
+            io_e.to_string(),
+            "deadline timed out for example.com after 1s"
+        );
+
+        // the connect timeout applies when it is sooner than the task's deadline
+        let start = tokio::time::Instant::now();
+        let deadline = start + Duration::from_secs(60);
+        let e = with_deadline(deadline, oneshot_connect(connector, url))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(
+            io_e.to_string(),
+            "connect timed out for example.com after 10s (timeout 10s)"
+        );
+    }
+
+    #[derive(Clone)]
+    struct NeverResolves;
+
+    impl Service<Name> for NeverResolves {
+        type Response = std::vec::IntoIter<std::net::SocketAddr>;
+        type Error = io::Error;
+        type Future = Pending<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _name: Name) -> Self::Future {
+            pending()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dns_timeout() {
+        let url: Uri = "http://example.com".parse().unwrap();
+
+        let start = tokio::time::Instant::now();
+        let http = HttpConnector::new_with_resolver(TimeoutResolver::new(NeverResolves));
+        let mut connector = TimeoutConnector::new(http);
+        connector.set_dns_timeout(Some(Duration::from_secs(2)));
+        connector.set_connect_timeout(Some(Duration::from_secs(10)));
+
+        let e = oneshot_connect(connector, url.clone()).await.err().unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        assert_eq!(timeout_phase(&*e), Some(Phase::Dns));
+
+        // the resolver's own timeout takes precedence
+        let mut resolver = TimeoutResolver::new(NeverResolves);
+        resolver.set_timeout(Some(Duration::from_secs(1)));
+        let mut connector = TimeoutConnector::new(HttpConnector::new_with_resolver(resolver));
+        connector.set_dns_timeout(Some(Duration::from_secs(2)));
+
+        let e = oneshot_connect(connector, url).await.err().unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+        assert_eq!(timeout_phase(&*e), Some(Phase::Dns));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_deadline() {
+        let url: Uri = "http://example.com".parse().unwrap();
+
+        let start = tokio::time::Instant::now();
+        let mut connector = TimeoutConnector::new(NeverConnects);
+        connector.set_connect_timeout(Some(Duration::from_secs(10)));
+        connector.set_connect_deadline(Some(start + Duration::from_secs(2)));
+
+        let e = oneshot_connect(connector.clone(), url.clone())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(
+            io_e.to_string(),
+            "deadline timed out for example.com after 2s"
+        );
+
+        // the deadline has passed, and the host is left out of redacted errors
+        connector.set_redact_errors(true);
+        let e = oneshot_connect(connector, url).await.err().unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(io_e.to_string(), "deadline timed out after 0ns");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_log_redacted() {
+        #[derive(Clone, Default)]
+        struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for SharedBuf {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let url: Uri = "http://example.com".parse().unwrap();
+        let buf = SharedBuf::default();
+        let mut connector = TimeoutConnector::new(NeverConnects);
+        connector.set_connect_timeout(Some(Duration::from_secs(1)));
+        connector.set_connect_log(buf.clone());
+
+        oneshot_connect(connector.clone(), url.clone())
+            .await
+            .err()
+            .unwrap();
+        connector.set_redact_errors(true);
+        oneshot_connect(connector, url).await.err().unwrap();
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let hosts: Vec<_> = out
+            .lines()
+            .map(|line| line.split(' ').nth(1).unwrap())
+            .collect();
+        assert_eq!(hosts, ["host=example.com", "host=-"]);
+        assert!(!out.lines().nth(1).unwrap().contains("example.com"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_error_mapper() {
+        let url: Uri = "http://example.com".parse().unwrap();
+
+        let mut connector = TimeoutConnector::new(NeverConnects);
+        connector.set_connect_timeout(Some(Duration::from_secs(1)));
+        connector.set_error_mapper(|e| format!("mapped: {}", e.phase()).into());
+
+        let e = oneshot_connect(connector, url).await.err().unwrap();
+        assert_eq!(e.to_string(), "mapped: connect");
+    }
+
+    #[tokio::test]
+    async fn test_denied_host() {
+        let url = "http://db.internal".parse().unwrap();
+
+        let http = HttpConnector::new();
+        let mut connector = TimeoutConnector::new(http);
+        connector.set_denied_hosts(["*.internal"]);
+
+        match oneshot_connect(connector, url).await {
+            Ok(_) => panic!("Expected the host to be denied"),
+            Err(e) => {
+                let denied = e.downcast_ref::<DeniedHost>().unwrap();
+                assert_eq!(denied.host(), "db.internal");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_denied_private_addr() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://{}", addr).parse().unwrap();
+
+        let http = HttpConnector::new();
+        let mut connector = TimeoutConnector::new(http);
+        connector.set_deny_private_addrs(true);
+
+        match oneshot_connect(connector.clone(), url).await {
+            Ok(_) => panic!("Expected the address to be denied"),
+            Err(e) => {
+                let denied = e.downcast_ref::<DeniedAddress>().unwrap();
+                assert_eq!(denied.addr(), Some(addr));
+            }
+        }
+
+        // refused before connecting, even though nothing listens there
+        let url = "http://[64:ff9b::a9fe:a9fe]".parse().unwrap();
+        match oneshot_connect(connector, url).await {
+            Ok(_) => panic!("Expected the address to be denied"),
+            Err(e) => {
+                let denied = e.downcast_ref::<DeniedAddress>().unwrap();
+                assert_eq!(
+                    denied.addr(),
+                    Some("[64:ff9b::a9fe:a9fe]:80".parse().unwrap())
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_denied_private_addr_resolved() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = format!("http://localhost:{}", addr.port()).parse().unwrap();
+
+        let resolver = PublicResolver::new(GaiResolver::new());
+        let connector = TimeoutConnector::new(HttpConnector::new_with_resolver(resolver));
+
+        // the connector itself does not deny private addresses, the resolver fails before connecting
+        match oneshot_connect(connector, url).await {
+            Ok(_) => panic!("Expected the address to be denied"),
+            Err(e) => {
+                let denied = e.source().unwrap().downcast_ref::<DeniedAddress>();
+                assert!(denied.unwrap().addr().unwrap().ip().is_loopback());
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_timeouts() {
+        let mut connector = TimeoutConnector::new(HttpConnector::new());
+        let internal = Timeouts {
+            connect: Some(Duration::from_millis(250)),
+            ..Timeouts::default()
+        };
+        let any = Timeouts {
+            connect: Some(Duration::from_secs(5)),
+            ..Timeouts::default()
+        };
+        connector.set_host_timeouts("*.internal", internal);
+        connector.set_host_timeouts("*", any);
+
+        assert_eq!(connector.host_timeouts(Some("db.internal")), internal);
+        assert_eq!(connector.host_timeouts(Some("example.com")), any);
+        assert_eq!(connector.host_timeouts(None), Timeouts::default());
+
+        let internal = Timeouts {
+            read: Some(Duration::from_secs(1)),
+            ..Timeouts::default()
+        };
+        connector.set_host_timeouts("*.internal", internal);
+        assert_eq!(connector.host_timeouts(Some("db.internal")), internal);
+    }
+
+    #[test]
+    fn test_scheme_timeouts() {
+        let mut connector = TimeoutConnector::new(HttpConnector::new());
+        let https = Timeouts {
+            connect: Some(Duration::from_secs(10)),
+            ..Timeouts::default()
+        };
+        connector.set_scheme_timeouts("HTTPS", https);
+
+        assert_eq!(connector.scheme_timeouts(Some("https")), https);
+        assert_eq!(connector.scheme_timeouts(Some("http")), Timeouts::default());
+        assert_eq!(connector.scheme_timeouts(None), Timeouts::default());
+    }
+
+    #[test]
+    fn test_timeout_policy() {
+        struct ReadPolicy;
+
+        impl TimeoutPolicy for ReadPolicy {
+            fn timeouts_for(&self, _dst: &Uri) -> Timeouts {
+                Timeouts {
+                    read: Some(Duration::from_secs(1)),
+                    ..Timeouts::default()
+                }
+            }
+        }
+
+        let mut connector = TimeoutConnector::new(HttpConnector::new());
+        connector.set_write_timeout(Some(Duration::from_secs(10)));
+        connector.set_host_timeouts(
+            "*.internal",
+            Timeouts {
+                connect: Some(Duration::from_secs(3)),
+                read: Some(Duration::from_secs(2)),
+                write: None,
+            },
+        );
+        connector.set_timeout_policy(ReadPolicy);
+
+        let dst = "http://db.internal".parse().unwrap();
+        assert_eq!(
+            connector.timeouts_for(&dst, Some("db.internal")),
+            Timeouts {
+                connect: Some(Duration::from_secs(3)),
+                read: Some(Duration::from_secs(1)),
+                write: Some(Duration::from_secs(10)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_timeout_fn() {
+        let mut connector = TimeoutConnector::new(HttpConnector::new());
+        connector.set_read_timeout(Some(Duration::from_secs(10)));
+        connector.set_timeout_fn(|dst| match dst.port_u16() {
+            Some(8080) => Timeouts {
+                read: Some(Duration::from_secs(1)),
+                ..Timeouts::default()
+            },
+            _ => Timeouts::default(),
+        });
+
+        let dst = "http://example.com:8080".parse().unwrap();
+        let timeouts = connector.timeouts_for(&dst, Some("example.com"));
+        assert_eq!(timeouts.read, Some(Duration::from_secs(1)));
+        let dst = "http://example.com".parse().unwrap();
+        let timeouts = connector.timeouts_for(&dst, Some("example.com"));
+        assert_eq!(timeouts.read, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_loopback_connect_precedence() {
+        let mut connector = TimeoutConnector::new(HttpConnector::new());
+        connector.set_connect_timeout(Some(Duration::from_secs(10)));
+        connector.set_loopback_connect_timeout(Some(Duration::from_millis(100)));
+        connector.set_host_timeouts(
+            "localhost",
+            Timeouts {
+                connect: Some(Duration::from_secs(3)),
+                ..Timeouts::default()
+            },
+        );
+        connector.set_host_timeouts(
+            "127.0.0.1",
+            Timeouts {
+                read: Some(Duration::from_secs(1)),
+                ..Timeouts::default()
+            },
+        );
+
+        // the host rule's connect timeout wins over the loopback one
+        let dst = "http://localhost:8080".parse().unwrap();
+        let timeouts = connector.timeouts_for(&dst, Some("localhost"));
+        assert_eq!(timeouts.connect, Some(Duration::from_secs(3)));
+
+        // a host rule without a connect timeout leaves the loopback one in place
+        let dst = "http://127.0.0.1:8080".parse().unwrap();
+        let timeouts = connector.timeouts_for(&dst, Some("127.0.0.1"));
+        assert_eq!(timeouts.connect, Some(Duration::from_millis(100)));
+        assert_eq!(timeouts.read, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_is_loopback() {
+        assert!(is_loopback(&"http://localhost:8080".parse().unwrap()));
+        assert!(is_loopback(&"http://LOCALHOST".parse().unwrap()));
+        assert!(is_loopback(&"http://127.0.0.1".parse().unwrap()));
+        assert!(is_loopback(&"http://127.1.2.3:80".parse().unwrap()));
+        assert!(is_loopback(&"http://[::1]:8080".parse().unwrap()));
+        assert!(!is_loopback(&"http://example.com".parse().unwrap()));
+        assert!(!is_loopback(&"http://10.255.255.1".parse().unwrap()));
+        assert!(!is_loopback(&"/path".parse().unwrap()));
+    }
 }
@@ -0,0 +1,25 @@
+//! The commonly used types of this crate, for glob importing.
+//!
+//! This also re-exports the hyper and tower types which appear in the connector's signatures.
+//!
+//! ```
+//! use hyper_timeout::prelude::*;
+//! # fn build<T>(connector: T) -> TimeoutConnector<T>
+//! # where
+//! #     T: Service<Uri> + Send,
+//! #     T::Response: hyper::rt::Read + hyper::rt::Write + Send + Unpin,
+//! #     T::Future: Send + 'static,
+//! #     T::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+//! # {
+//! #     TimeoutConnector::new(connector)
+//! # }
+//! ```
+
+pub use crate::{
+    BoxTimeoutStream, Budget, DeniedAddress, DeniedHost, HostKey, HostPattern, Phase,
+    PublicResolver, TimeoutBody, TimeoutConnector, TimeoutError, TimeoutKind, TimeoutPolicy,
+    TimeoutResolver, TimeoutStream, Timeouts,
+};
+pub use hyper::Uri;
+pub use hyper_util::client::legacy::connect::Connection;
+pub use tower_service::Service;
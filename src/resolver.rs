@@ -0,0 +1,326 @@
+//! A resolver wrapper which applies a timeout to name resolution.
+//!
+//! `HttpConnector` resolves names with the blocking system resolver, which can take up the whole connect timeout on
+//! its own. Wrapping the resolver in a [`TimeoutResolver`] bounds resolution separately, and a resolution timeout
+//! is reported by `HttpConnector` as a DNS error rather than as a connect timeout. A resolver without a timeout of its
+//! own uses the one set with [`TimeoutConnector::set_dns_timeout`](crate::TimeoutConnector::set_dns_timeout) on the
+//! connector wrapping it.
+//!
+//! [`PublicResolver`] removes private addresses from the resolved addresses, so `HttpConnector` never opens a
+//! connection to them.
+#![warn(missing_docs)]
+
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::vec;
+
+use hyper_util::client::legacy::connect::dns::Name;
+use tokio::time::timeout;
+use tower_service::Service;
+
+use crate::host::is_private_addr;
+use crate::{BoxError, DeniedAddress, Phase, TimeoutError};
+
+tokio::task_local! {
+    // the DNS timeout of the connector which is connecting
+    static DNS_TIMEOUT: Option<Duration>;
+}
+
+/// Runs `fut`, resolving through a [`TimeoutResolver`] without a timeout of its own with `timeout`.
+pub(crate) fn with_dns_timeout<F: Future>(
+    timeout: Option<Duration>,
+    fut: F,
+) -> impl Future<Output = F::Output> {
+    DNS_TIMEOUT.scope(timeout, fut)
+}
+
+/// A resolver which applies a timeout to each name resolution.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use hyper_timeout::TimeoutResolver;
+/// use hyper_util::client::legacy::connect::{dns::GaiResolver, HttpConnector};
+///
+/// let mut resolver = TimeoutResolver::new(GaiResolver::new());
+/// resolver.set_timeout(Some(Duration::from_secs(2)));
+/// let http = HttpConnector::new_with_resolver(resolver);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimeoutResolver<R> {
+    resolver: R,
+    timeout: Option<Duration>,
+}
+
+impl<R> TimeoutResolver<R> {
+    /// Returns a new `TimeoutResolver` wrapping the specified resolver.
+    ///
+    /// There is initially no timeout, so the DNS timeout of the connector, if any, applies.
+    pub fn new(resolver: R) -> TimeoutResolver<R> {
+        TimeoutResolver {
+            resolver,
+            timeout: None,
+        }
+    }
+
+    /// Returns the current resolution timeout.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the resolution timeout.
+    ///
+    /// This takes precedence over the DNS timeout of the connector.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns a shared reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Returns a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.resolver
+    }
+
+    /// Consumes the `TimeoutResolver`, returning the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.resolver
+    }
+}
+
+impl<R> Service<Name> for TimeoutResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Future: Send + 'static,
+    R::Error: Into<BoxError>,
+{
+    type Response = R::Response;
+    type Error = BoxError;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.resolver.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolve_timeout = self
+            .timeout
+            .or_else(|| DNS_TIMEOUT.try_with(|timeout| *timeout).ok().flatten());
+        let resolving = self.resolver.call(name);
+
+        let fut = async move {
+            match resolve_timeout {
+                None => resolving.await.map_err(Into::into),
+                Some(resolve_timeout) => {
+                    let resolving = timeout(resolve_timeout, resolving).await.map_err(|_| {
+                        let err = TimeoutError::new(Phase::Dns).with_timeout(Some(resolve_timeout));
+                        io::Error::from(err)
+                    })?;
+                    resolving.map_err(Into::into)
+                }
+            }
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// A resolver which drops private addresses from the addresses returned by the inner resolver.
+///
+/// [`TimeoutConnector::set_deny_private_addrs`](crate::TimeoutConnector::set_deny_private_addrs) can only check the
+/// remote address once a connection has been made. Resolving through a `PublicResolver` instead rejects private
+/// addresses before connecting, so no connection is ever opened to them. If every resolved address is private,
+/// resolution fails with a [`DeniedAddress`] error for the first of them.
+///
+/// ```
+/// use hyper_timeout::{PublicResolver, TimeoutConnector, TimeoutResolver};
+/// use hyper_util::client::legacy::connect::{dns::GaiResolver, HttpConnector};
+///
+/// let resolver = PublicResolver::new(TimeoutResolver::new(GaiResolver::new()));
+/// let mut connector = TimeoutConnector::new(HttpConnector::new_with_resolver(resolver));
+/// // still checks the address actually connected to
+/// connector.set_deny_private_addrs(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PublicResolver<R> {
+    resolver: R,
+    redact_errors: bool,
+}
+
+impl<R> PublicResolver<R> {
+    /// Returns a new `PublicResolver` wrapping the specified resolver.
+    pub fn new(resolver: R) -> PublicResolver<R> {
+        PublicResolver {
+            resolver,
+            redact_errors: false,
+        }
+    }
+
+    /// Leave the denied address out of the message of the [`DeniedAddress`] error.
+    ///
+    /// Set this along with [`TimeoutConnector::set_redact_errors`](crate::TimeoutConnector::set_redact_errors), as the
+    /// connector cannot redact errors coming from the resolver. The address is still available from
+    /// [`DeniedAddress::addr`].
+    ///
+    /// Default is false.
+    pub fn set_redact_errors(&mut self, redact: bool) {
+        self.redact_errors = redact;
+    }
+
+    /// Returns a shared reference to the inner resolver.
+    pub fn get_ref(&self) -> &R {
+        &self.resolver
+    }
+
+    /// Returns a mutable reference to the inner resolver.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.resolver
+    }
+
+    /// Consumes the `PublicResolver`, returning the inner resolver.
+    pub fn into_inner(self) -> R {
+        self.resolver
+    }
+}
+
+impl<R> Service<Name> for PublicResolver<R>
+where
+    R: Service<Name>,
+    R::Response: Iterator<Item = SocketAddr>,
+    R::Future: Send + 'static,
+    R::Error: Into<BoxError>,
+{
+    type Response = vec::IntoIter<SocketAddr>;
+    type Error = BoxError;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.resolver.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let redact_errors = self.redact_errors;
+        let resolving = self.resolver.call(name);
+
+        let fut = async move {
+            let mut denied = None;
+            let addrs: Vec<_> = resolving
+                .await
+                .map_err(Into::into)?
+                .filter(|addr| {
+                    let private = is_private_addr(addr.ip());
+                    if private && denied.is_none() {
+                        denied = Some(*addr);
+                    }
+                    !private
+                })
+                .collect();
+            match denied {
+                Some(addr) if addrs.is_empty() => {
+                    Err(DeniedAddress::new(Some(addr)).redact(redact_errors).into())
+                }
+                _ => Ok(addrs.into_iter()),
+            }
+        };
+
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::Infallible;
+    use std::str::FromStr;
+
+    #[derive(Clone)]
+    struct PendingResolver;
+
+    impl Service<Name> for PendingResolver {
+        type Response = vec::IntoIter<SocketAddr>;
+        type Error = Infallible;
+        type Future = std::future::Pending<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _name: Name) -> Self::Future {
+            std::future::pending()
+        }
+    }
+
+    #[derive(Clone)]
+    struct StaticResolver(Vec<SocketAddr>);
+
+    impl Service<Name> for StaticResolver {
+        type Response = vec::IntoIter<SocketAddr>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _name: Name) -> Self::Future {
+            std::future::ready(Ok(self.0.clone().into_iter()))
+        }
+    }
+
+    #[tokio::test]
+    async fn public_resolver() {
+        let public: SocketAddr = "93.184.216.34:0".parse().unwrap();
+        let private: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        let metadata: SocketAddr = "[::ffff:169.254.169.254]:0".parse().unwrap();
+        let name = Name::from_str("example.com").unwrap();
+
+        let mut resolver = PublicResolver::new(StaticResolver(vec![private, public, metadata]));
+        let addrs: Vec<_> = resolver.call(name.clone()).await.unwrap().collect();
+        assert_eq!(addrs, [public]);
+
+        let mut resolver = PublicResolver::new(StaticResolver(vec![metadata, private]));
+        let e = resolver.call(name.clone()).await.unwrap_err();
+        let denied = e.downcast_ref::<DeniedAddress>().unwrap();
+        assert_eq!(denied.addr(), Some(metadata));
+        assert!(denied.to_string().contains("169.254.169.254"));
+    }
+
+    #[tokio::test]
+    async fn public_resolver_redact() {
+        let private: SocketAddr = "10.0.0.1:0".parse().unwrap();
+        let mut resolver = PublicResolver::new(StaticResolver(vec![private]));
+        resolver.set_redact_errors(true);
+
+        let e = resolver
+            .call(Name::from_str("example.com").unwrap())
+            .await
+            .unwrap_err();
+        let denied = e.downcast_ref::<DeniedAddress>().unwrap();
+        assert!(!denied.to_string().contains("10.0.0.1"));
+        assert_eq!(denied.addr(), Some(private));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resolve_timeout() {
+        let mut resolver = TimeoutResolver::new(PendingResolver);
+        resolver.set_timeout(Some(Duration::from_secs(2)));
+
+        let e = resolver
+            .call(Name::from_str("example.com").unwrap())
+            .await
+            .unwrap_err();
+        let io_e = e.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(io_e.to_string(), "dns resolution timed out (timeout 2s)");
+    }
+}
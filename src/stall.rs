@@ -0,0 +1,151 @@
+//! Detection of streams which stay open but stop transferring data.
+//!
+//! [`StallDetector`] has no timer or IO of its own. The caller reports how many bytes were transferred and when, and
+//! asks whether the stream has stalled, so the same check can be applied to any kind of stream. The connector applies it
+//! to downloads with [`TimeoutConnector::set_stall_detector`](crate::TimeoutConnector::set_stall_detector).
+#![warn(missing_docs)]
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Detects a stream transferring fewer than a minimum number of bytes over a sliding window.
+///
+/// ```
+/// use std::time::{Duration, Instant};
+///
+/// use hyper_timeout::StallDetector;
+///
+/// // at least 1 KiB every 10 seconds
+/// let mut detector = StallDetector::new(Duration::from_secs(10), 1024);
+/// let start = Instant::now();
+/// detector.record(512, start);
+/// assert!(!detector.is_stalled(start + Duration::from_secs(5)));
+/// assert!(detector.is_stalled(start + Duration::from_secs(10)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StallDetector {
+    window: Duration,
+    min_bytes: u64,
+    start: Option<Instant>,
+    samples: VecDeque<(Instant, u64)>,
+    bytes: u64,
+}
+
+impl StallDetector {
+    /// Returns a new `StallDetector` requiring at least `min_bytes` to be transferred in every `window`.
+    pub fn new(window: Duration, min_bytes: u64) -> StallDetector {
+        StallDetector {
+            window,
+            min_bytes,
+            start: None,
+            samples: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    /// Returns the length of the sliding window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the minimum number of bytes to transfer in each window.
+    pub fn min_bytes(&self) -> u64 {
+        self.min_bytes
+    }
+
+    /// Reports that `bytes` were transferred at `now`.
+    ///
+    /// The first report starts the measurement, so a report of zero bytes can be used to start it before any data
+    /// has been transferred.
+    pub fn record(&mut self, bytes: u64, now: Instant) {
+        self.start.get_or_insert(now);
+        if bytes > 0 {
+            self.samples.push_back((now, bytes));
+            self.bytes = self.bytes.saturating_add(bytes);
+        }
+        self.expire(now);
+    }
+
+    /// Returns the number of bytes transferred in the window ending at `now`.
+    pub fn bytes_in_window(&mut self, now: Instant) -> u64 {
+        self.expire(now);
+        self.bytes
+    }
+
+    /// Returns true if fewer than the minimum number of bytes were transferred in the window ending at `now`.
+    ///
+    /// A stream is never considered stalled before a whole window has passed since the measurement started.
+    pub fn is_stalled(&mut self, now: Instant) -> bool {
+        let start = match self.start {
+            Some(start) => start,
+            None => return false,
+        };
+        now.saturating_duration_since(start) >= self.window
+            && self.bytes_in_window(now) < self.min_bytes
+    }
+
+    /// Forgets every report, so the measurement starts again with the next one.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.samples.clear();
+        self.bytes = 0;
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, bytes)) = self.samples.front() {
+            if now.saturating_duration_since(at) < self.window {
+                break;
+            }
+            self.samples.pop_front();
+            self.bytes = self.bytes.saturating_sub(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn not_started() {
+        let mut detector = StallDetector::new(Duration::from_secs(1), 1);
+        assert!(!detector.is_stalled(Instant::now()));
+    }
+
+    #[test]
+    fn sliding_window() {
+        let start = Instant::now();
+        let mut detector = StallDetector::new(Duration::from_secs(10), 100);
+        detector.record(0, start);
+        assert!(!detector.is_stalled(start + Duration::from_secs(9)));
+        assert!(detector.is_stalled(start + Duration::from_secs(10)));
+
+        detector.record(60, start + Duration::from_secs(10));
+        detector.record(60, start + Duration::from_secs(15));
+        assert_eq!(
+            detector.bytes_in_window(start + Duration::from_secs(15)),
+            120
+        );
+        assert!(!detector.is_stalled(start + Duration::from_secs(15)));
+
+        // the first report has left the window
+        assert_eq!(
+            detector.bytes_in_window(start + Duration::from_secs(20)),
+            60
+        );
+        assert!(detector.is_stalled(start + Duration::from_secs(20)));
+    }
+
+    #[test]
+    fn reset() {
+        let start = Instant::now();
+        let mut detector = StallDetector::new(Duration::from_secs(1), 1);
+        detector.record(0, start);
+        assert!(detector.is_stalled(start + Duration::from_secs(2)));
+
+        detector.reset();
+        assert!(!detector.is_stalled(start + Duration::from_secs(2)));
+        detector.record(0, start + Duration::from_secs(2));
+        assert!(!detector.is_stalled(start + Duration::from_secs(2)));
+    }
+}
@@ -4,7 +4,12 @@
 //!
 //! These timeouts are analogous to the read and write timeouts on traditional blocking sockets. A timeout countdown is
 //! initiated when a read/write operation returns [`Poll::Pending`]. If a read/write does not return successfully before
-//! the countdown expires, an [`io::Error`] with a kind of [`TimedOut`](io::ErrorKind::TimedOut) is returned.
+//! the countdown expires, an [`io::Error`] with a kind of [`TimedOut`](io::ErrorKind::TimedOut) is returned. Its inner
+//! error is a [`TimeoutError`] telling which timeout expired.
+//!
+//! The poll methods do not depend on how often or by which task they are polled. Spurious wakeups and inner streams
+//! which wake themselves do not restart a countdown that is already running, and the timer always wakes the waker
+//! passed to the most recent poll.
 #![warn(missing_docs)]
 
 use hyper::rt::{Read, ReadBuf, ReadBufCursor, Write};
@@ -13,53 +18,64 @@ use pin_project_lite::pin_project;
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
-use std::task::{ready, Context, Poll};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll, Waker};
 use std::time::Duration;
 use tokio::time::{sleep_until, Instant, Sleep};
 
+use crate::error::{Phase, TimeoutError};
+use crate::stall::StallDetector;
+use crate::timeout_core::{Action, TimeoutCore};
+
 pin_project! {
     #[derive(Debug)]
-    struct TimeoutState {
-        timeout: Option<Duration>,
+    pub(crate) struct TimeoutState {
+        phase: Phase,
+        // named in the error when the timeout expires, unless it is redacted
+        host: Option<Arc<str>>,
+        core: TimeoutCore,
         #[pin]
         cur: Sleep,
-        active: bool,
+        // the waker the timer was last polled with, cleared whenever the deadline moves
+        waker: Option<Waker>,
     }
 }
 
 impl TimeoutState {
     #[inline]
-    fn new() -> TimeoutState {
+    pub(crate) fn new(phase: Phase) -> TimeoutState {
         TimeoutState {
-            timeout: None,
+            phase,
+            host: None,
+            core: TimeoutCore::new(),
             cur: sleep_until(Instant::now()),
-            active: false,
+            waker: None,
         }
     }
 
     #[inline]
-    fn timeout(&self) -> Option<Duration> {
-        self.timeout
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.core.timeout()
     }
 
     #[inline]
-    fn set_timeout(&mut self, timeout: Option<Duration>) {
+    pub(crate) fn set_timeout(&mut self, timeout: Option<Duration>) {
         // since this takes &mut self, we can't yet be active
-        self.timeout = timeout;
+        self.core.set_timeout(timeout);
     }
 
     #[inline]
-    fn set_timeout_pinned(mut self: Pin<&mut Self>, timeout: Option<Duration>) {
-        *self.as_mut().project().timeout = timeout;
+    pub(crate) fn set_timeout_pinned(mut self: Pin<&mut Self>, timeout: Option<Duration>) {
+        self.as_mut().project().core.set_timeout(timeout);
         self.reset();
     }
 
     #[inline]
-    fn reset(self: Pin<&mut Self>) {
+    pub(crate) fn reset(self: Pin<&mut Self>) {
         let this = self.project();
 
-        if *this.active {
-            *this.active = false;
+        if this.core.on_progress() {
+            *this.waker = None;
             this.cur.reset(Instant::now());
         }
     }
@@ -68,37 +84,87 @@ impl TimeoutState {
     fn restart(self: Pin<&mut Self>) {
         let this = self.project();
 
-        if *this.active {
-            let timeout = match this.timeout {
-                Some(timeout) => *timeout,
-                None => return,
-            };
-
-            this.cur.reset(Instant::now() + timeout);
+        if let Some(deadline) = this.core.restart(Instant::now().into_std()) {
+            *this.waker = None;
+            this.cur.reset(Instant::from_std(deadline));
         }
     }
 
+    /// Starts the countdown at `start`, whether or not an operation is pending.
+    #[inline]
+    pub(crate) fn start(self: Pin<&mut Self>, start: Instant) {
+        // the timer is moved to the new deadline the next time it is polled
+        self.project().core.start(start.into_std());
+    }
+
+    /// Stops the countdown.
     #[inline]
-    fn poll_check(self: Pin<&mut Self>, cx: &mut Context) -> io::Result<()> {
+    pub(crate) fn stop(self: Pin<&mut Self>) {
+        self.project().core.on_progress();
+    }
+
+    /// Returns an error if the operation has been pending for too long, starting the countdown if it is not running.
+    #[inline]
+    pub(crate) fn poll_check(mut self: Pin<&mut Self>, cx: &mut Context) -> io::Result<()> {
+        let action = self
+            .as_mut()
+            .project()
+            .core
+            .on_pending(Instant::now().into_std());
+        self.poll_action(action, cx)
+    }
+
+    /// Returns an error if the running countdown has expired, without starting one.
+    #[inline]
+    pub(crate) fn poll_expired(mut self: Pin<&mut Self>, cx: &mut Context) -> io::Result<()> {
+        let action = self.core.check(Instant::now().into_std());
+        self.as_mut().poll_action(action, cx)
+    }
+
+    #[inline]
+    fn poll_action(self: Pin<&mut Self>, action: Action, cx: &mut Context) -> io::Result<()> {
         let mut this = self.project();
 
-        let timeout = match this.timeout {
-            Some(timeout) => *timeout,
-            None => return Ok(()),
+        let deadline = match action {
+            Action::Idle => return Ok(()),
+            Action::TimedOut => return Err(timeout_error(*this.phase, this.host, this.core)),
+            Action::WakeAt(deadline) => Instant::from_std(deadline),
         };
 
-        if !*this.active {
-            this.cur.as_mut().reset(Instant::now() + timeout);
-            *this.active = true;
+        if this.cur.deadline() != deadline {
+            this.cur.as_mut().reset(deadline);
+            *this.waker = None;
+        }
+
+        // The timer already holds this waker and will use it when the deadline passes, so there is nothing to
+        // register until then.
+        if let Some(waker) = this.waker {
+            if waker.will_wake(cx.waker()) {
+                return Ok(());
+            }
         }
 
         match this.cur.poll(cx) {
-            Poll::Ready(()) => Err(io::Error::from(io::ErrorKind::TimedOut)),
-            Poll::Pending => Ok(()),
+            Poll::Ready(()) => Err(timeout_error(*this.phase, this.host, this.core)),
+            Poll::Pending => {
+                *this.waker = Some(cx.waker().clone());
+                Ok(())
+            }
         }
     }
 }
 
+/// Returns the error for an expired countdown, measuring how long it ran from when it started.
+fn timeout_error(phase: Phase, host: &Option<Arc<str>>, core: &TimeoutCore) -> io::Error {
+    let mut err = TimeoutError::new(phase)
+        .with_host(host.as_deref())
+        .with_timeout(core.timeout());
+    if let Some(started) = core.started() {
+        err = err.with_elapsed(Instant::now().into_std().saturating_duration_since(started));
+    }
+    err.into()
+}
+
 pin_project! {
     /// An `hyper::rt::Read`er which applies a timeout to read operations.
     #[derive(Debug)]
@@ -121,7 +187,7 @@ where
     pub fn new(reader: R) -> TimeoutReader<R> {
         TimeoutReader {
             reader,
-            state: TimeoutState::new(),
+            state: TimeoutState::new(Phase::Read),
             reset_on_write: false,
         }
     }
@@ -262,6 +328,7 @@ pin_project! {
         writer: W,
         #[pin]
         state: TimeoutState,
+        max_write_size: Option<usize>,
     }
 }
 
@@ -275,7 +342,8 @@ where
     pub fn new(writer: W) -> TimeoutWriter<W> {
         TimeoutWriter {
             writer,
-            state: TimeoutState::new(),
+            state: TimeoutState::new(Phase::Write),
+            max_write_size: None,
         }
     }
 
@@ -300,6 +368,19 @@ where
         self.project().state.set_timeout_pinned(timeout);
     }
 
+    /// Returns the maximum number of bytes passed to a single write on the inner writer.
+    pub fn max_write_size(&self) -> Option<usize> {
+        self.max_write_size
+    }
+
+    /// Sets the maximum number of bytes passed to a single write on the inner writer.
+    ///
+    /// Larger buffers are written in pieces, so the write timeout measures the progress of each piece instead of
+    /// waiting for one large write to complete. A size of zero is treated as one.
+    pub fn set_max_write_size(&mut self, size: Option<usize>) {
+        self.max_write_size = size.map(|size| size.max(1));
+    }
+
     /// Returns a shared reference to the inner writer.
     pub fn get_ref(&self) -> &W {
         &self.writer
@@ -331,6 +412,10 @@ where
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
         let this = self.project();
+        let buf = match *this.max_write_size {
+            Some(max) if buf.len() > max => &buf[..max],
+            _ => buf,
+        };
         let r = this.writer.poll_write(cx, buf);
         match r {
             Poll::Pending => this.state.poll_check(cx)?,
@@ -364,6 +449,14 @@ where
         cx: &mut Context,
         bufs: &[io::IoSlice],
     ) -> Poll<io::Result<usize>> {
+        if self.max_write_size.is_some() {
+            let buf = bufs
+                .iter()
+                .find(|b| !b.is_empty())
+                .map_or(&[][..], |b| &**b);
+            return self.poll_write(cx, buf);
+        }
+
         let this = self.project();
         let r = this.writer.poll_write_vectored(cx, bufs);
         match r {
@@ -374,7 +467,7 @@ where
     }
 
     fn is_write_vectored(&self) -> bool {
-        self.writer.is_write_vectored()
+        self.max_write_size.is_none() && self.writer.is_write_vectored()
     }
 }
 
@@ -393,13 +486,73 @@ where
 
 pin_project! {
     /// A stream which applies read and write timeouts to an inner stream.
+    ///
+    /// # Using with tokio IO
+    ///
+    /// `TimeoutStream` implements hyper's IO traits. After an upgrade or a `CONNECT`, the stream can be handed to
+    /// tokio-based code, such as a tokio-util `Framed` codec, by wrapping it in [`TokioIo`], which implements tokio's
+    /// `AsyncRead` and `AsyncWrite`. Every read and write still goes through the `TimeoutStream`, so its timeouts keep
+    /// applying. The boxed stream returned by the connector is `Unpin`, so it can be wrapped as is.
+    ///
+    /// ```
+    /// # async fn run() -> std::io::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// use hyper_timeout::TimeoutStream;
+    /// use hyper_util::rt::TokioIo;
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    ///
+    /// let tcp = tokio::net::TcpStream::connect("127.0.0.1:8080").await?;
+    /// let mut stream = TimeoutStream::new(TokioIo::new(tcp));
+    /// stream.set_read_timeout(Some(Duration::from_secs(5)));
+    ///
+    /// let mut io = TokioIo::new(Box::pin(stream));
+    /// io.write_all(b"ping").await?;
+    /// let mut buf = [0; 4];
+    /// io.read_exact(&mut buf).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`TokioIo`]: https://docs.rs/hyper-util/latest/hyper_util/rt/tokio/struct.TokioIo.html
     #[derive(Debug)]
     pub struct TimeoutStream<S> {
         #[pin]
-        stream: TimeoutReader<TimeoutWriter<S>>
+        stream: TimeoutReader<TimeoutWriter<S>>,
+        // counts down while no read or write makes progress
+        #[pin]
+        idle: TimeoutState,
+        // counts down between writing a request and reading the first byte of the response
+        #[pin]
+        first_byte: TimeoutState,
+        awaiting_first_byte: bool,
+        // bound the current upload or download as a whole, only the one for the current direction is running
+        transfer: Transfer,
+        #[pin]
+        upload: TimeoutState,
+        #[pin]
+        download: TimeoutState,
+        // measures the bytes read during the current download
+        stall: Option<StallDetector>,
+        #[pin]
+        shutdown: TimeoutState,
+        // restarted whenever the stream makes progress, whether or not it is being polled
+        #[pin]
+        silence: TimeoutState,
+        // fires once regardless of activity on the stream
+        #[pin]
+        deadline: TimeoutState,
     }
 }
 
+/// Which direction a `TimeoutStream` last made progress in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Idle,
+    Upload,
+    Download,
+}
+
 impl<S> TimeoutStream<S>
 where
     S: Read + Write,
@@ -410,7 +563,19 @@ where
     pub fn new(stream: S) -> TimeoutStream<S> {
         let writer = TimeoutWriter::new(stream);
         let stream = TimeoutReader::new(writer);
-        TimeoutStream { stream }
+        TimeoutStream {
+            stream,
+            idle: TimeoutState::new(Phase::Idle),
+            first_byte: TimeoutState::new(Phase::FirstByte),
+            awaiting_first_byte: false,
+            transfer: Transfer::Idle,
+            upload: TimeoutState::new(Phase::Upload),
+            download: TimeoutState::new(Phase::Download),
+            stall: None,
+            shutdown: TimeoutState::new(Phase::Shutdown),
+            silence: TimeoutState::new(Phase::Silence),
+            deadline: TimeoutState::new(Phase::Deadline),
+        }
     }
 
     /// Returns the current read timeout.
@@ -458,6 +623,145 @@ where
             .set_timeout_pinned(timeout)
     }
 
+    /// Returns the current idle timeout.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle.timeout()
+    }
+
+    /// Sets the idle timeout.
+    ///
+    /// The idle countdown runs while an operation is pending and is stopped by progress in either direction, so it
+    /// only expires once neither reads nor writes have completed for the whole timeout. Unlike the read timeout, it
+    /// does not interrupt a long response which arrives in slow bursts while requests are still being written.
+    ///
+    /// This can only be used before the stream is pinned; use
+    /// [`set_idle_timeout_pinned`](Self::set_idle_timeout_pinned) otherwise.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle.set_timeout(timeout)
+    }
+
+    /// Sets the idle timeout.
+    ///
+    /// This will reset any pending idle timeout. Use [`set_idle_timeout`](Self::set_idle_timeout) instead if the
+    /// stream has not yet been pinned.
+    pub fn set_idle_timeout_pinned(self: Pin<&mut Self>, timeout: Option<Duration>) {
+        self.project().idle.set_timeout_pinned(timeout)
+    }
+
+    /// Returns the current first byte timeout.
+    pub fn first_byte_timeout(&self) -> Option<Duration> {
+        self.first_byte.timeout()
+    }
+
+    /// Sets the first byte timeout.
+    ///
+    /// After a write, the next read must return within this timeout. Once a read has returned, only the read
+    /// timeout applies until the next write, so a server which is slow to start responding can be cut off without
+    /// also limiting how slowly the rest of the response may arrive.
+    ///
+    /// This can only be used before the stream is pinned; use
+    /// [`set_first_byte_timeout_pinned`](Self::set_first_byte_timeout_pinned) otherwise.
+    pub fn set_first_byte_timeout(&mut self, timeout: Option<Duration>) {
+        self.first_byte.set_timeout(timeout)
+    }
+
+    /// Sets the first byte timeout.
+    ///
+    /// This will reset any pending first byte timeout. Use [`set_first_byte_timeout`](Self::set_first_byte_timeout)
+    /// instead if the stream has not yet been pinned.
+    pub fn set_first_byte_timeout_pinned(self: Pin<&mut Self>, timeout: Option<Duration>) {
+        self.project().first_byte.set_timeout_pinned(timeout)
+    }
+
+    /// Returns the current upload timeout.
+    pub fn upload_timeout(&self) -> Option<Duration> {
+        self.upload.timeout()
+    }
+
+    /// Sets the upload timeout.
+    ///
+    /// An upload starts with the first write after a read, or after the stream was created, and lasts until the next
+    /// read returns. Unlike the write timeout, which bounds each write, this bounds the upload as a whole. It takes
+    /// effect from the next upload.
+    pub fn set_upload_timeout(&mut self, timeout: Option<Duration>) {
+        self.upload.set_timeout(timeout);
+    }
+
+    /// Returns the current download timeout.
+    pub fn download_timeout(&self) -> Option<Duration> {
+        self.download.timeout()
+    }
+
+    /// Sets the download timeout.
+    ///
+    /// A download starts with the first read which returns after a write, or after the stream was created, and lasts
+    /// until the next write. Unlike the read timeout, which bounds each read, this bounds the download as a whole. It
+    /// takes effect from the next download.
+    ///
+    /// The stream cannot tell where a response ends, so a download only ends with the next request. A pooled
+    /// connection which stays idle for longer than the download timeout is closed the next time it is read.
+    pub fn set_download_timeout(&mut self, timeout: Option<Duration>) {
+        self.download.set_timeout(timeout);
+    }
+
+    /// Returns the current stall detector.
+    pub fn stall_detector(&self) -> Option<&StallDetector> {
+        self.stall.as_ref()
+    }
+
+    /// Sets a stall detector, failing a download which reads too few bytes.
+    ///
+    /// The detector is reset whenever a download starts, as with the download timeout, and is fed the number of bytes
+    /// each read returns. Once a read finds the download stalled, it fails with a
+    /// [`TimedOut`](io::ErrorKind::TimedOut) error. A read which stays pending does not feed the detector, so set a
+    /// read or idle timeout as well to catch a download which stops altogether.
+    pub fn set_stall_detector(&mut self, detector: Option<StallDetector>) {
+        self.stall = detector;
+    }
+
+    /// Returns the current shutdown timeout.
+    pub fn shutdown_timeout(&self) -> Option<Duration> {
+        self.shutdown.timeout()
+    }
+
+    /// Sets the shutdown timeout.
+    ///
+    /// If shutting down the inner stream, including any TLS `close_notify`, does not complete within this timeout,
+    /// `poll_shutdown` fails with a [`TimedOut`](io::ErrorKind::TimedOut) error so the stream can be dropped instead
+    /// of waiting on an unresponsive peer.
+    ///
+    /// This can only be used before the stream is pinned; use
+    /// [`set_shutdown_timeout_pinned`](Self::set_shutdown_timeout_pinned) otherwise.
+    pub fn set_shutdown_timeout(&mut self, timeout: Option<Duration>) {
+        self.shutdown.set_timeout(timeout)
+    }
+
+    /// Sets the shutdown timeout.
+    ///
+    /// This will reset any pending shutdown timeout. Use [`set_shutdown_timeout`](Self::set_shutdown_timeout) instead
+    /// if the stream has not yet been pinned.
+    pub fn set_shutdown_timeout_pinned(self: Pin<&mut Self>, timeout: Option<Duration>) {
+        self.project().shutdown.set_timeout_pinned(timeout)
+    }
+
+    /// Returns the current maximum silence.
+    pub fn max_silence(&self) -> Option<Duration> {
+        self.silence.timeout()
+    }
+
+    /// Sets the maximum silence.
+    ///
+    /// The stream fails once it has made no progress in either direction for this long, counting from the last
+    /// progress or from this call. Unlike the other timeouts, the countdown does not wait for an operation to be
+    /// pending, so it also catches a stream which is held without being polled. The failure is reported by the next
+    /// operation on the stream, and the task which last polled the stream is woken when the countdown expires.
+    ///
+    /// This can only be used before the stream is pinned.
+    pub fn set_max_silence(&mut self, timeout: Option<Duration>) {
+        self.silence.core.set_timeout(timeout);
+        self.silence.core.start(Instant::now().into_std());
+    }
+
     /// Reset on the reader timeout on write
     ///
     /// This will reset the reader timeout when a write is done through the
@@ -467,6 +771,69 @@ where
         self.stream.set_reset_on_write(reset);
     }
 
+    /// Returns the maximum number of bytes passed to a single write on the inner stream.
+    pub fn max_write_size(&self) -> Option<usize> {
+        self.stream.get_ref().max_write_size()
+    }
+
+    /// Sets the maximum number of bytes passed to a single write on the inner stream.
+    ///
+    /// Larger buffers are written in pieces, so the write timeout measures the progress of each piece instead of
+    /// waiting for one large write to complete.
+    pub fn set_max_write_size(&mut self, size: Option<usize>) {
+        self.stream.get_mut().set_max_write_size(size)
+    }
+
+    /// Sets the host named in the errors of the stream's timeouts.
+    pub(crate) fn set_error_host(&mut self, host: Option<&str>) {
+        let host: Option<Arc<str>> = host.map(Into::into);
+        for state in [
+            &mut self.stream.state,
+            &mut self.stream.reader.state,
+            &mut self.idle,
+            &mut self.first_byte,
+            &mut self.upload,
+            &mut self.download,
+            &mut self.shutdown,
+            &mut self.silence,
+            &mut self.deadline,
+        ] {
+            state.host = host.clone();
+        }
+    }
+
+    /// Returns the absolute deadline of the stream, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline.core.deadline().map(Instant::from_std)
+    }
+
+    /// Sets a deadline after which every operation on the stream fails, however active it is. Its errors report
+    /// the time elapsed since `started`.
+    ///
+    /// This can only be used before the stream is pinned.
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Instant>, started: Instant) {
+        match deadline {
+            Some(deadline) => {
+                let core = &mut self.deadline.core;
+                core.start_until(started.into_std(), deadline.into_std());
+            }
+            None => {
+                self.deadline.core.on_progress();
+            }
+        }
+    }
+
+    /// Arms an absolute deadline, after which every operation on the stream fails with a
+    /// [`TimedOut`](io::ErrorKind::TimedOut) error, however active the stream is.
+    ///
+    /// This replaces any deadline set before, including one from the connector's total timeout. The deadline is
+    /// measured on the tokio clock and takes effect from the next operation on the stream.
+    pub fn set_absolute_deadline(self: Pin<&mut Self>, deadline: Instant) {
+        let now = Instant::now().into_std();
+        let state = self.project().deadline.project();
+        state.core.start_until(now, deadline.into_std());
+    }
+
     /// Returns a shared reference to the inner stream.
     pub fn get_ref(&self) -> &S {
         self.stream.get_ref().get_ref()
@@ -497,10 +864,70 @@ where
         cx: &mut Context,
         buf: ReadBufCursor,
     ) -> Poll<Result<(), io::Error>> {
-        self.project().stream.poll_read(cx, buf)
+        let mut this = self.project();
+        this.deadline.poll_expired(cx)?;
+        this.silence.as_mut().poll_expired(cx)?;
+        this.upload.as_mut().poll_expired(cx)?;
+        this.download.as_mut().poll_expired(cx)?;
+        let r = match this.stall {
+            Some(detector) => {
+                let (r, n) = poll_read_counted(this.stream.as_mut(), cx, buf);
+                if *this.transfer != Transfer::Download {
+                    detector.reset();
+                }
+                let now = Instant::now().into_std();
+                detector.record(n as u64, now);
+                if detector.is_stalled(now) {
+                    // the stall detector is part of the download, which is named in errors the same way
+                    let err = TimeoutError::new(Phase::Stall)
+                        .with_host(this.download.host.as_deref())
+                        .with_timeout(Some(detector.window()));
+                    return Poll::Ready(Err(err.into()));
+                }
+                r
+            }
+            None => this.stream.as_mut().poll_read(cx, buf),
+        };
+        match r {
+            Poll::Pending => this.idle.poll_check(cx)?,
+            _ => {
+                this.idle.reset();
+                this.silence.restart();
+            }
+        }
+        if r.is_ready() && *this.transfer != Transfer::Download {
+            *this.transfer = Transfer::Download;
+            this.upload.stop();
+            this.download.start(Instant::now());
+        }
+        if *this.awaiting_first_byte {
+            match r {
+                Poll::Pending => this.first_byte.poll_check(cx)?,
+                _ => {
+                    *this.awaiting_first_byte = false;
+                    this.first_byte.reset();
+                }
+            }
+        }
+        r
     }
 }
 
+/// Reads from `reader`, also returning the number of bytes read.
+fn poll_read_counted<R: Read>(
+    reader: Pin<&mut R>,
+    cx: &mut Context,
+    mut buf: ReadBufCursor,
+) -> (Poll<io::Result<()>>, usize) {
+    // SAFETY: the inner `ReadBuf` only hands out the unfilled part of `buf`, and never de-initializes it.
+    let mut inner = ReadBuf::uninit(unsafe { buf.as_mut() });
+    let r = reader.poll_read(cx, inner.unfilled());
+    let n = inner.filled().len();
+    // SAFETY: the first `n` bytes of the unfilled part of `buf` were filled, and so initialized, by the reader.
+    unsafe { buf.advance(n) };
+    (r, n)
+}
+
 impl<S> Write for TimeoutStream<S>
 where
     S: Read + Write,
@@ -510,15 +937,65 @@ where
         cx: &mut Context,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
-        self.project().stream.poll_write(cx, buf)
+        let mut this = self.project();
+        this.deadline.poll_expired(cx)?;
+        this.silence.as_mut().poll_expired(cx)?;
+        this.upload.as_mut().poll_expired(cx)?;
+        let r = this.stream.poll_write(cx, buf);
+        match r {
+            Poll::Pending => this.idle.poll_check(cx)?,
+            _ => {
+                this.idle.reset();
+                this.silence.restart();
+            }
+        }
+        if r.is_ready() {
+            // the request is still being written, so the wait for the response starts over
+            *this.awaiting_first_byte = true;
+            this.first_byte.reset();
+            if *this.transfer != Transfer::Upload {
+                *this.transfer = Transfer::Upload;
+                this.download.stop();
+                this.upload.start(Instant::now());
+            }
+        }
+        r
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
-        self.project().stream.poll_flush(cx)
+        let mut this = self.project();
+        this.deadline.poll_expired(cx)?;
+        this.silence.as_mut().poll_expired(cx)?;
+        this.upload.as_mut().poll_expired(cx)?;
+        let r = this.stream.poll_flush(cx);
+        match r {
+            Poll::Pending => this.idle.poll_check(cx)?,
+            _ => {
+                this.idle.reset();
+                this.silence.restart();
+            }
+        }
+        r
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), io::Error>> {
-        self.project().stream.poll_shutdown(cx)
+        let mut this = self.project();
+        this.deadline.poll_expired(cx)?;
+        this.silence.as_mut().poll_expired(cx)?;
+        this.upload.as_mut().poll_expired(cx)?;
+        let r = this.stream.poll_shutdown(cx);
+        match r {
+            Poll::Pending => {
+                this.idle.poll_check(cx)?;
+                this.shutdown.poll_check(cx)?;
+            }
+            _ => {
+                this.idle.reset();
+                this.shutdown.reset();
+                this.silence.restart();
+            }
+        }
+        r
     }
 
     fn poll_write_vectored(
@@ -526,7 +1003,29 @@ where
         cx: &mut Context,
         bufs: &[io::IoSlice],
     ) -> Poll<io::Result<usize>> {
-        self.project().stream.poll_write_vectored(cx, bufs)
+        let mut this = self.project();
+        this.deadline.poll_expired(cx)?;
+        this.silence.as_mut().poll_expired(cx)?;
+        this.upload.as_mut().poll_expired(cx)?;
+        let r = this.stream.poll_write_vectored(cx, bufs);
+        match r {
+            Poll::Pending => this.idle.poll_check(cx)?,
+            _ => {
+                this.idle.reset();
+                this.silence.restart();
+            }
+        }
+        if r.is_ready() {
+            // the request is still being written, so the wait for the response starts over
+            *this.awaiting_first_byte = true;
+            this.first_byte.reset();
+            if *this.transfer != Transfer::Upload {
+                *this.transfer = Transfer::Upload;
+                this.download.stop();
+                this.upload.start(Instant::now());
+            }
+        }
+        r
     }
 
     fn is_write_vectored(&self) -> bool {
@@ -552,6 +1051,24 @@ where
     }
 }
 
+impl<R> Connection for TimeoutReader<R>
+where
+    R: Connection,
+{
+    fn connected(&self) -> Connected {
+        self.reader.connected()
+    }
+}
+
+impl<W> Connection for TimeoutWriter<W>
+where
+    W: Connection,
+{
+    fn connected(&self) -> Connected {
+        self.writer.connected()
+    }
+}
+
 pin_project! {
     /// A future which can be used to easily read available number of bytes to fill
     /// a buffer. Based on the internal [tokio::io::util::read::Read]
@@ -688,9 +1205,13 @@ mod test {
     use hyper_util::rt::TokioIo;
     use std::io::Write;
     use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{RawWaker, RawWakerVTable, Wake};
     use std::thread;
     use tokio::net::TcpStream;
     use tokio::pin;
+    use tokio::time::sleep;
 
     pin_project! {
         struct DelayStream {
@@ -741,6 +1262,99 @@ mod test {
         }
     }
 
+    struct YieldStream;
+
+    impl Read for YieldStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context,
+            _buf: ReadBufCursor,
+        ) -> Poll<Result<(), io::Error>> {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    struct SilentStream;
+
+    impl Read for SilentStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context,
+            _buf: ReadBufCursor,
+        ) -> Poll<Result<(), io::Error>> {
+            Poll::Pending
+        }
+    }
+
+    impl hyper::rt::Write for SilentStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    struct HangingShutdown;
+
+    impl Read for HangingShutdown {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context,
+            _buf: ReadBufCursor,
+        ) -> Poll<Result<(), io::Error>> {
+            Poll::Pending
+        }
+    }
+
+    impl hyper::rt::Write for HangingShutdown {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<Result<usize, io::Error>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), io::Error>> {
+            Poll::Pending
+        }
+    }
+
+    struct CountWaker(AtomicUsize);
+
+    impl CountWaker {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Wake for CountWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn count_waker() -> (Arc<CountWaker>, Waker) {
+        let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        (count, waker)
+    }
+
     #[tokio::test]
     async fn read_timeout() {
         let reader = DelayStream::new(Instant::now() + Duration::from_millis(500));
@@ -762,6 +1376,156 @@ mod test {
         reader.read(&mut [0]).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn read_timeout_spurious_wakeups() {
+        let reader = DelayStream::new(Instant::now() + Duration::from_secs(10));
+        let mut reader = TimeoutReader::new(reader);
+        reader.set_timeout(Some(Duration::from_millis(100)));
+        pin!(reader);
+
+        let (_, waker) = count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0u8; 1];
+        for _ in 0..10 {
+            let mut buf = ReadBuf::new(&mut data);
+            assert!(reader
+                .as_mut()
+                .poll_read(&mut cx, buf.unfilled())
+                .is_pending());
+        }
+
+        sleep(Duration::from_millis(150)).await;
+
+        let mut buf = ReadBuf::new(&mut data);
+        match reader.as_mut().poll_read(&mut cx, buf.unfilled()) {
+            Poll::Ready(Err(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            r => panic!("unexpected poll result: {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_timeout_wakes_latest_waker() {
+        let reader = DelayStream::new(Instant::now() + Duration::from_secs(10));
+        let mut reader = TimeoutReader::new(reader);
+        reader.set_timeout(Some(Duration::from_millis(100)));
+        pin!(reader);
+
+        let (first, first_waker) = count_waker();
+        let (second, second_waker) = count_waker();
+        let mut data = [0u8; 1];
+
+        let mut buf = ReadBuf::new(&mut data);
+        let mut cx = Context::from_waker(&first_waker);
+        assert!(reader
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+
+        let mut buf = ReadBuf::new(&mut data);
+        let mut cx = Context::from_waker(&second_waker);
+        assert!(reader
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+
+        sleep(Duration::from_millis(150)).await;
+
+        assert_eq!(first.count(), 0);
+        assert!(second.count() > 0);
+    }
+
+    /// Returns a waker which counts how many times it is cloned.
+    fn clone_count_waker() -> (&'static AtomicUsize, Waker) {
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        unsafe fn clone(data: *const ()) -> RawWaker {
+            (*(data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+            RawWaker::new(data, &VTABLE)
+        }
+
+        unsafe fn noop(_data: *const ()) {}
+
+        let clones: &'static AtomicUsize = Box::leak(Box::new(AtomicUsize::new(0)));
+        let raw = RawWaker::new(clones as *const AtomicUsize as *const (), &VTABLE);
+        // SAFETY: the vtable functions only touch the leaked counter, which lives forever
+        let waker = unsafe { Waker::from_raw(raw) };
+        (clones, waker)
+    }
+
+    #[tokio::test]
+    async fn read_timeout_registers_waker_once() {
+        let mut reader = TimeoutReader::new(SilentStream);
+        reader.set_timeout(Some(Duration::from_millis(100)));
+        pin!(reader);
+
+        let (clones, waker) = clone_count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0u8; 1];
+        for _ in 0..100 {
+            let mut buf = ReadBuf::new(&mut data);
+            assert!(reader
+                .as_mut()
+                .poll_read(&mut cx, buf.unfilled())
+                .is_pending());
+        }
+
+        // one clone held by the timer and one kept to compare later wakers against, rather than one per poll
+        assert_eq!(clones.load(Ordering::SeqCst), 2);
+
+        sleep(Duration::from_millis(150)).await;
+        let mut buf = ReadBuf::new(&mut data);
+        match reader.as_mut().poll_read(&mut cx, buf.unfilled()) {
+            Poll::Ready(Err(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            r => panic!("unexpected poll result: {:?}", r),
+        }
+    }
+
+    #[tokio::test]
+    async fn read_timeout_self_waking() {
+        let mut reader = TimeoutReader::new(YieldStream);
+        reader.set_timeout(Some(Duration::from_millis(50)));
+        pin!(reader);
+
+        let r = reader.read(&mut [0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_timeout_advance_past_deadlines() {
+        let mut readers = Vec::new();
+        for timeout in [100, 200, 300, 1_000] {
+            let reader = DelayStream::new(Instant::now() + Duration::from_secs(10));
+            let mut reader = TimeoutReader::new(reader);
+            reader.set_timeout(Some(Duration::from_millis(timeout)));
+            readers.push(Box::pin(reader));
+        }
+
+        let (_, waker) = count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0u8; 1];
+        for reader in readers.iter_mut() {
+            let mut buf = ReadBuf::new(&mut data);
+            assert!(reader
+                .as_mut()
+                .poll_read(&mut cx, buf.unfilled())
+                .is_pending());
+        }
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+
+        let timed_out: Vec<bool> = readers
+            .iter_mut()
+            .map(|reader| {
+                let mut buf = ReadBuf::new(&mut data);
+                match reader.as_mut().poll_read(&mut cx, buf.unfilled()) {
+                    Poll::Ready(Err(e)) => e.kind() == io::ErrorKind::TimedOut,
+                    _ => false,
+                }
+            })
+            .collect();
+        assert_eq!(timed_out, [true, true, true, false]);
+    }
+
     #[tokio::test]
     async fn write_timeout() {
         let writer = DelayStream::new(Instant::now() + Duration::from_millis(500));
@@ -783,6 +1547,246 @@ mod test {
         writer.write(&[0]).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn write_max_size() {
+        let writer = DelayStream::new(Instant::now());
+        let mut writer = TimeoutWriter::new(writer);
+        writer.set_max_write_size(Some(4));
+        pin!(writer);
+
+        assert_eq!(writer.write(&[0; 10]).await.unwrap(), 4);
+        assert_eq!(writer.write(&[0; 2]).await.unwrap(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stream_deadline() {
+        let stream = DelayStream::new(Instant::now());
+        let mut stream = TimeoutStream::new(stream);
+        stream.set_write_timeout(Some(Duration::from_millis(100)));
+        stream.set_deadline(
+            Some(Instant::now() + Duration::from_millis(250)),
+            Instant::now(),
+        );
+        pin!(stream);
+
+        // the stream stays active, so only the deadline can stop it
+        for _ in 0..2 {
+            stream.write(&[0]).await.unwrap();
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        stream.write(&[0]).await.unwrap();
+        tokio::time::advance(Duration::from_millis(100)).await;
+
+        let r = stream.write(&[0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn absolute_deadline() {
+        let stream = TimeoutStream::new(DelayStream::new(Instant::now()));
+        pin!(stream);
+        assert_eq!(stream.deadline(), None);
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        stream.as_mut().set_absolute_deadline(deadline);
+        assert_eq!(stream.deadline(), Some(deadline));
+        stream.write(&[0]).await.unwrap();
+
+        tokio::time::advance(Duration::from_millis(100)).await;
+        let r = stream.write(&[0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_timeout() {
+        let mut stream = TimeoutStream::new(SilentStream);
+        stream.set_idle_timeout(Some(Duration::from_millis(100)));
+        pin!(stream);
+
+        let (_, waker) = count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0u8; 1];
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(stream
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+
+        // writing is progress, so the pending read does not time out
+        tokio::time::advance(Duration::from_millis(60)).await;
+        stream.write(&[0]).await.unwrap();
+        tokio::time::advance(Duration::from_millis(60)).await;
+
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(stream
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+
+        tokio::time::advance(Duration::from_millis(110)).await;
+
+        let mut buf = ReadBuf::new(&mut data);
+        match stream.as_mut().poll_read(&mut cx, buf.unfilled()) {
+            Poll::Ready(Err(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            r => panic!("unexpected poll result: {:?}", r),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn first_byte_timeout() {
+        let mut stream = TimeoutStream::new(SilentStream);
+        stream.set_first_byte_timeout(Some(Duration::from_millis(100)));
+        pin!(stream);
+
+        let (_, waker) = count_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0u8; 1];
+
+        // nothing has been written, so nothing is awaited
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(stream
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+        tokio::time::advance(Duration::from_millis(200)).await;
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(stream
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+
+        stream.write(&[0]).await.unwrap();
+        let mut buf = ReadBuf::new(&mut data);
+        assert!(stream
+            .as_mut()
+            .poll_read(&mut cx, buf.unfilled())
+            .is_pending());
+        tokio::time::advance(Duration::from_millis(110)).await;
+
+        let mut buf = ReadBuf::new(&mut data);
+        match stream.as_mut().poll_read(&mut cx, buf.unfilled()) {
+            Poll::Ready(Err(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+                assert_eq!(
+                    e.to_string(),
+                    "first byte timed out after 110ms (timeout 100ms)"
+                );
+            }
+            r => panic!("unexpected poll result: {:?}", r),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upload_timeout() {
+        let mut stream = TimeoutStream::new(SilentStream);
+        stream.set_upload_timeout(Some(Duration::from_millis(250)));
+        pin!(stream);
+
+        // every write completes, but the upload as a whole takes too long
+        for _ in 0..2 {
+            stream.write(&[0]).await.unwrap();
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        stream.write(&[0]).await.unwrap();
+        tokio::time::advance(Duration::from_millis(100)).await;
+
+        let r = stream.write(&[0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn download_timeout() {
+        let stream = DelayStream::new(Instant::now());
+        let mut stream = TimeoutStream::new(stream);
+        stream.set_download_timeout(Some(Duration::from_millis(250)));
+        pin!(stream);
+
+        for _ in 0..3 {
+            stream.read(&mut [0]).await.unwrap();
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        let r = stream.read(&mut [0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+
+        // a new request starts a new upload
+        stream.write(&[0]).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stall_detector() {
+        use tokio::io::AsyncWriteExt;
+
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut stream = TimeoutStream::new(TokioIo::new(client));
+        stream.set_stall_detector(Some(StallDetector::new(Duration::from_secs(1), 10)));
+        pin!(stream);
+
+        // one byte every 600ms is too slow once a whole window has passed
+        server.write_all(b"a").await.unwrap();
+        assert_eq!(stream.read(&mut [0; 8]).await.unwrap(), 1);
+        tokio::time::advance(Duration::from_millis(600)).await;
+        server.write_all(b"b").await.unwrap();
+        assert_eq!(stream.read(&mut [0; 8]).await.unwrap(), 1);
+        tokio::time::advance(Duration::from_millis(600)).await;
+        server.write_all(b"c").await.unwrap();
+        let e = stream.read(&mut [0; 8]).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(e.to_string(), "download stall timed out (timeout 1s)");
+
+        // a new request starts a new download, and with it a new measurement
+        stream.write(&[0]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(2)).await;
+        server.write_all(b"d").await.unwrap();
+        assert_eq!(stream.read(&mut [0; 8]).await.unwrap(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_timeout() {
+        let mut stream = TimeoutStream::new(HangingShutdown);
+        stream.set_shutdown_timeout(Some(Duration::from_millis(100)));
+        pin!(stream);
+
+        let r =
+            std::future::poll_fn(|cx| hyper::rt::Write::poll_shutdown(stream.as_mut(), cx)).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn max_silence() {
+        let mut stream = TimeoutStream::new(SilentStream);
+        stream.set_max_silence(Some(Duration::from_millis(100)));
+        pin!(stream);
+
+        tokio::time::advance(Duration::from_millis(60)).await;
+        stream.write(&[0]).await.unwrap();
+
+        // nothing is pending while the stream is not polled, but the silence still counts
+        tokio::time::advance(Duration::from_millis(110)).await;
+        let r = stream.write(&[0]).await;
+        assert_eq!(r.err().unwrap().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tokio_io() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut stream = TimeoutStream::new(TokioIo::new(client));
+        stream.set_read_timeout(Some(Duration::from_millis(100)));
+        let mut io = TokioIo::new(Box::pin(stream));
+
+        io.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"pong").await.unwrap();
+        io.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+
+        // the read timeout still applies through the tokio traits
+        let e = io.read(&mut buf).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
     #[tokio::test]
     async fn tcp_read() {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
@@ -810,4 +1814,33 @@ mod test {
             Err(e) => panic!("{:?}", e),
         }
     }
+
+    #[tokio::test]
+    async fn boxed_client_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let mut socket = listener.accept().unwrap().0;
+            let mut buf = [0; 1024];
+            std::io::Read::read(&mut socket, &mut buf).unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let s = TcpStream::connect(&addr).await.unwrap();
+        let mut s = TimeoutStream::new(TokioIo::new(s));
+        s.set_read_timeout(Some(Duration::from_secs(5)));
+        let s: Pin<Box<TimeoutStream<_>>> = Box::pin(s);
+        assert!(!s.connected().is_proxied());
+
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(s).await.unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::get("/")
+            .body(http_body_util::Empty::<hyper::body::Bytes>::new())
+            .unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), 200);
+    }
 }
@@ -0,0 +1,227 @@
+//! A sans-IO state machine for timeouts.
+//!
+//! [`TimeoutCore`] holds the countdown logic used by every timeout on the streams and bodies in this crate, without
+//! any timer or IO of its own. The caller reports when an operation is pending or has made progress, passing in the
+//! current time, and is told when to be woken up and whether the timeout has expired. The same logic can then drive
+//! timeouts on other transports, and be tested without a runtime.
+#![warn(missing_docs)]
+
+use std::time::{Duration, Instant};
+
+/// What the caller of [`TimeoutCore::on_pending`] should do next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// No timeout is set, so there is nothing to wait for.
+    Idle,
+    /// The timeout has not expired yet. The caller should arrange to be woken at the given instant.
+    WakeAt(Instant),
+    /// The timeout has expired.
+    TimedOut,
+}
+
+/// The countdown for a single timeout.
+///
+/// For timeouts on an operation, such as a read, the countdown starts when the operation is first reported as pending
+/// and stops when the operation makes progress. Reporting the operation as pending again while the countdown is
+/// running does not move the deadline.
+///
+/// Timeouts which run regardless of pending operations, such as a deadline for a whole stream, are started
+/// explicitly with [`start`](Self::start) or [`start_until`](Self::start_until) and checked with
+/// [`check`](Self::check), which never starts a countdown.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutCore {
+    timeout: Option<Duration>,
+    started: Option<Instant>,
+    deadline: Option<Instant>,
+}
+
+impl TimeoutCore {
+    /// Returns a new `TimeoutCore`.
+    ///
+    /// There is initially no timeout.
+    pub fn new() -> TimeoutCore {
+        TimeoutCore::default()
+    }
+
+    /// Returns the current timeout.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Sets the timeout.
+    ///
+    /// A countdown which is already running keeps its deadline until the operation makes progress.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns the deadline of the running countdown, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns when the running countdown started, if any.
+    pub fn started(&self) -> Option<Instant> {
+        self.started
+    }
+
+    /// Returns true if a countdown is running.
+    pub fn is_active(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Reports that the operation is pending at `now`.
+    ///
+    /// This starts the countdown if it is not already running.
+    pub fn on_pending(&mut self, now: Instant) -> Action {
+        if self.deadline.is_none() {
+            self.start(now);
+        }
+        self.check(now)
+    }
+
+    /// Returns what to do next at `now`, without starting the countdown.
+    ///
+    /// This is [`Action::Idle`] unless a countdown is running.
+    pub fn check(&self, now: Instant) -> Action {
+        match self.deadline {
+            None => Action::Idle,
+            Some(deadline) if now >= deadline => Action::TimedOut,
+            Some(deadline) => Action::WakeAt(deadline),
+        }
+    }
+
+    /// Reports that the operation made progress, stopping the countdown.
+    ///
+    /// Returns true if a countdown was running.
+    pub fn on_progress(&mut self) -> bool {
+        self.started = None;
+        self.deadline.take().is_some()
+    }
+
+    /// Starts the countdown at `start`, replacing any countdown which is running.
+    ///
+    /// Returns the new deadline, or `None` if no timeout is set, in which case no countdown is left running.
+    pub fn start(&mut self, start: Instant) -> Option<Instant> {
+        // a deadline too far away to represent will never be reached
+        self.deadline = self.timeout.and_then(|timeout| start.checked_add(timeout));
+        self.started = self.deadline.map(|_| start);
+        self.deadline
+    }
+
+    /// Starts a countdown at `start` which runs until `deadline`, whatever the timeout, replacing any countdown which
+    /// is running.
+    pub fn start_until(&mut self, start: Instant, deadline: Instant) {
+        self.started = Some(start);
+        self.deadline = Some(deadline);
+    }
+
+    /// Restarts a running countdown from `now`.
+    ///
+    /// Returns the new deadline, or `None` if no countdown was restarted.
+    pub fn restart(&mut self, now: Instant) -> Option<Instant> {
+        self.deadline?;
+        self.start(now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn idle_without_timeout() {
+        let mut core = TimeoutCore::new();
+        assert_eq!(core.on_pending(Instant::now()), Action::Idle);
+        assert!(!core.is_active());
+    }
+
+    #[test]
+    fn pending_keeps_deadline() {
+        let start = Instant::now();
+        let mut core = TimeoutCore::new();
+        core.set_timeout(Some(Duration::from_secs(1)));
+
+        let deadline = start + Duration::from_secs(1);
+        assert_eq!(core.on_pending(start), Action::WakeAt(deadline));
+        assert_eq!(
+            core.on_pending(start + Duration::from_millis(500)),
+            Action::WakeAt(deadline)
+        );
+        assert_eq!(core.on_pending(deadline), Action::TimedOut);
+    }
+
+    #[test]
+    fn progress_stops_countdown() {
+        let start = Instant::now();
+        let mut core = TimeoutCore::new();
+        core.set_timeout(Some(Duration::from_secs(1)));
+
+        core.on_pending(start);
+        assert!(core.on_progress());
+        assert!(!core.on_progress());
+
+        let later = start + Duration::from_secs(5);
+        assert_eq!(
+            core.on_pending(later),
+            Action::WakeAt(later + Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn restart_moves_deadline() {
+        let start = Instant::now();
+        let mut core = TimeoutCore::new();
+        core.set_timeout(Some(Duration::from_secs(1)));
+        assert_eq!(core.restart(start), None);
+
+        core.on_pending(start);
+        let later = start + Duration::from_millis(800);
+        assert_eq!(core.restart(later), Some(later + Duration::from_secs(1)));
+        assert_eq!(
+            core.on_pending(start + Duration::from_secs(1)),
+            Action::WakeAt(later + Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn check_does_not_start() {
+        let start = Instant::now();
+        let mut core = TimeoutCore::new();
+        core.set_timeout(Some(Duration::from_secs(1)));
+        assert_eq!(core.check(start), Action::Idle);
+        assert!(!core.is_active());
+
+        let deadline = start + Duration::from_secs(1);
+        assert_eq!(core.start(start), Some(deadline));
+        assert_eq!(core.started(), Some(start));
+        assert_eq!(core.check(start), Action::WakeAt(deadline));
+        assert_eq!(core.check(deadline), Action::TimedOut);
+
+        // starting again replaces the running countdown
+        let later = start + Duration::from_millis(500);
+        assert_eq!(core.start(later), Some(later + Duration::from_secs(1)));
+        assert_eq!(
+            core.check(deadline),
+            Action::WakeAt(later + Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn start_until_ignores_timeout() {
+        let start = Instant::now();
+        let deadline = start + Duration::from_secs(5);
+        let mut core = TimeoutCore::new();
+        core.start_until(start, deadline);
+        assert_eq!(core.timeout(), None);
+        assert_eq!(core.check(start), Action::WakeAt(deadline));
+        assert_eq!(core.check(deadline), Action::TimedOut);
+    }
+
+    #[test]
+    fn huge_timeout_never_expires() {
+        let mut core = TimeoutCore::new();
+        core.set_timeout(Some(Duration::MAX));
+        assert_eq!(core.on_pending(Instant::now()), Action::Idle);
+    }
+}
@@ -0,0 +1,137 @@
+//! Sets of timeouts which override the connector's defaults for some destinations.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::Uri;
+
+/// Connect, read and write timeouts which override those of a [`TimeoutConnector`](crate::TimeoutConnector).
+///
+/// A timeout left as `None` falls back to the connector's own setting.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use hyper_timeout::{TimeoutConnector, Timeouts};
+/// use hyper_util::client::legacy::connect::HttpConnector;
+///
+/// let mut connector = TimeoutConnector::new(HttpConnector::new());
+/// connector.set_read_timeout(Some(Duration::from_secs(30)));
+/// connector.set_host_timeouts(
+///     "*.internal",
+///     Timeouts {
+///         connect: Some(Duration::from_millis(250)),
+///         read: Some(Duration::from_secs(2)),
+///         ..Timeouts::default()
+///     },
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    /// The timeout for connecting.
+    pub connect: Option<Duration>,
+    /// The timeout for each read.
+    pub read: Option<Duration>,
+    /// The timeout for each write.
+    pub write: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Returns these timeouts, falling back to `other` for any which are not set.
+    pub fn or(self, other: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(other.connect),
+            read: self.read.or(other.read),
+            write: self.write.or(other.write),
+        }
+    }
+}
+
+/// Chooses the timeouts for each destination the connector connects to.
+///
+/// A policy is set on the connector with [`set_timeout_policy`](crate::TimeoutConnector::set_timeout_policy) and
+/// consulted on every call, so timeouts can come from configuration which changes at runtime. Closures taking a
+/// `&Uri` are policies too, see [`set_timeout_fn`](crate::TimeoutConnector::set_timeout_fn).
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use hyper::Uri;
+/// use hyper_timeout::{TimeoutPolicy, Timeouts};
+///
+/// struct Tenants;
+///
+/// impl TimeoutPolicy for Tenants {
+///     fn timeouts_for(&self, dst: &Uri) -> Timeouts {
+///         match dst.host() {
+///             Some(host) if host.ends_with(".batch.example.com") => Timeouts {
+///                 read: Some(Duration::from_secs(300)),
+///                 ..Timeouts::default()
+///             },
+///             _ => Timeouts::default(),
+///         }
+///     }
+/// }
+/// ```
+pub trait TimeoutPolicy: Send + Sync {
+    /// Returns the timeouts for connecting to `dst`.
+    ///
+    /// A timeout left as `None` falls back to the connector's own configuration.
+    fn timeouts_for(&self, dst: &Uri) -> Timeouts;
+}
+
+impl<F> TimeoutPolicy for F
+where
+    F: Fn(&Uri) -> Timeouts + Send + Sync,
+{
+    fn timeouts_for(&self, dst: &Uri) -> Timeouts {
+        self(dst)
+    }
+}
+
+/// A shared [`TimeoutPolicy`] which can be stored in a connector.
+#[derive(Clone)]
+pub(crate) struct Policy(Arc<dyn TimeoutPolicy>);
+
+impl Policy {
+    pub(crate) fn new(policy: impl TimeoutPolicy + 'static) -> Policy {
+        Policy(Arc::new(policy))
+    }
+
+    pub(crate) fn timeouts_for(&self, dst: &Uri) -> Timeouts {
+        self.0.timeouts_for(dst)
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Policy")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn or() {
+        let a = Timeouts {
+            connect: Some(Duration::from_secs(1)),
+            ..Timeouts::default()
+        };
+        let b = Timeouts {
+            connect: Some(Duration::from_secs(5)),
+            read: Some(Duration::from_secs(30)),
+            write: None,
+        };
+        assert_eq!(
+            a.or(b),
+            Timeouts {
+                connect: Some(Duration::from_secs(1)),
+                read: Some(Duration::from_secs(30)),
+                write: None,
+            }
+        );
+    }
+}
@@ -96,7 +96,7 @@ async fn test_upload_timeout() {
         let bytes = frame
             .expect("frame error")
             .into_data()
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Error when consuming frame"))
+            .map_err(|_| io::Error::other("Error when consuming frame"))
             .expect("data error");
         resp_body.extend_from_slice(&bytes);
     }
@@ -0,0 +1,88 @@
+//! A long running test which churns through connections that time out, checking that tasks and memory stay bounded.
+//!
+//! This is ignored by default. Run it with:
+//!
+//! ```text
+//! cargo test --release --test soak -- --ignored --nocapture
+//! ```
+//!
+//! Set `SOAK_ITERATIONS` to change the number of requests made.
+
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use hyper_util::rt::TokioExecutor;
+use tokio::net::TcpListener;
+
+use hyper_timeout::TimeoutConnector;
+
+/// Returns the resident set size of this process in pages, if it is available.
+fn resident_pages() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    statm.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[tokio::test]
+#[ignore]
+async fn soak_read_timeouts() {
+    let iterations = env::var("SOAK_ITERATIONS")
+        .ok()
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(100_000);
+
+    let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .expect("Failed to bind listener");
+    let addr = listener.local_addr().unwrap();
+
+    // accept connections and never respond, so every request hits the read timeout
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _addr)) = listener.accept().await {
+            held.push(stream);
+            if held.len() >= 1024 {
+                held.clear();
+            }
+        }
+    });
+
+    let mut connector = TimeoutConnector::new(HttpConnector::new());
+    connector.set_connect_timeout(Some(Duration::from_secs(1)));
+    connector.set_read_timeout(Some(Duration::from_millis(1)));
+    let client = Client::builder(TokioExecutor::new()).build::<_, Empty<Bytes>>(connector);
+    let uri: hyper::Uri = format!("http://{}/", addr).parse().unwrap();
+
+    let warmup = iterations / 10;
+    let mut baseline = None;
+    for i in 0..iterations {
+        let res = client.get(uri.clone()).await;
+        assert!(res.is_err(), "Expected a timeout");
+
+        if i == warmup {
+            baseline = resident_pages();
+        }
+    }
+
+    // give the connection tasks a chance to finish
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let alive = tokio::runtime::Handle::current()
+        .metrics()
+        .num_alive_tasks();
+    eprintln!("alive tasks after {} requests: {}", iterations, alive);
+    assert!(alive < 16, "{} tasks are still alive", alive);
+
+    if let (Some(baseline), Some(end)) = (baseline, resident_pages()) {
+        eprintln!("resident pages: {} after warmup, {} at end", baseline, end);
+        assert!(
+            end < baseline * 2,
+            "resident memory grew from {} to {} pages",
+            baseline,
+            end
+        );
+    }
+}